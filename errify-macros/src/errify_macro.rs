@@ -60,3 +60,38 @@ impl ToTokens for ErrifyWithMacro {
         self.output.to_tokens(tokens)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use quote::quote;
+
+    use super::*;
+
+    #[test]
+    fn cold_fn() {
+        let args = quote! { cold, "context {arg}" };
+        let input = quote! {
+            fn func(arg: i32) -> Result<i32, Error> {
+                Ok(arg)
+            }
+        };
+
+        let tokens = errify_impl(args, input).unwrap().to_string();
+        assert!(tokens.contains("# [cold] # [inline (never)] fn __errify_cold"));
+        assert!(tokens.contains("__errify_cold (err ,"));
+    }
+
+    #[test]
+    fn cold_fn_with() {
+        let args = quote! { cold, || "context" };
+        let input = quote! {
+            fn func(arg: i32) -> Result<i32, Error> {
+                Ok(arg)
+            }
+        };
+
+        let tokens = errify_with_impl(args, input).unwrap().to_string();
+        assert!(tokens.contains("# [cold] # [inline (never)] fn __errify_cold"));
+        assert!(tokens.contains("__errify_cold (err ,"));
+    }
+}
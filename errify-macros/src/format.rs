@@ -0,0 +1,400 @@
+//! Lightweight parsing of format strings, see [`std::fmt`] syntax.
+
+use std::fmt::{self, Display, Formatter, Write};
+
+/// Argument referenced by a format string placeholder.
+pub enum FormatArg {
+    Next,
+    Index(usize),
+    Name(String),
+}
+
+impl FormatArg {
+    fn parse(arg: &str) -> Option<Self> {
+        if arg.is_empty() {
+            Some(Self::Next)
+        } else if let Ok(index) = arg.parse() {
+            Some(Self::Index(index))
+        } else if arg.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            Some(Self::Name(arg.to_owned()))
+        } else {
+            None
+        }
+    }
+}
+
+enum Piece {
+    /// Text with escaped braces as is.
+    Text(String),
+    /// `{arg:spec}`
+    Placeholder { arg: String, spec: Option<String> },
+}
+
+pub struct FormatString {
+    pieces: Vec<Piece>,
+}
+
+impl FormatString {
+    /// Parses the format string, `None` if the format string is malformed.
+    pub fn parse(fmt: &str) -> Option<Self> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = fmt.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' | '}' if chars.peek() == Some(&c) => {
+                    chars.next();
+                    text.push(c);
+                    text.push(c);
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next()? {
+                            '}' => break,
+                            c => placeholder.push(c),
+                        }
+                    }
+                    let (arg, spec) = match placeholder.split_once(':') {
+                        Some((arg, spec)) => (arg.trim(), Some(spec.to_owned())),
+                        None => (placeholder.trim(), None),
+                    };
+                    FormatArg::parse(arg)?;
+
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    pieces.push(Piece::Placeholder {
+                        arg: arg.to_owned(),
+                        spec,
+                    });
+                }
+                '}' => return None,
+                c => text.push(c),
+            }
+        }
+        pieces.push(Piece::Text(text));
+
+        Some(Self { pieces })
+    }
+
+    /// Collects the arguments referenced by the format string in order of use.
+    pub fn args(&self) -> Vec<FormatArg> {
+        let mut res = Vec::new();
+        for piece in &self.pieces {
+            let Piece::Placeholder { arg, spec } = piece else {
+                continue;
+            };
+
+            if let Some(spec) = spec {
+                // Width and precision parameters, `{:1$}`, `{:.prec$}` or `{:.*}`
+                res.extend(
+                    spec_params(spec)
+                        .into_iter()
+                        .filter_map(|(start, end)| FormatArg::parse(&spec[start..end])),
+                );
+                if spec.contains(".*") {
+                    res.push(FormatArg::Next);
+                }
+            }
+            res.extend(FormatArg::parse(arg));
+        }
+        res
+    }
+
+    /// Replaces all argument references with names returned by `f(arg, is_count)`,
+    /// where `arg` is either [`FormatArg::Index`] or [`FormatArg::Name`] and `is_count` is `true`
+    /// for width and precision parameters.
+    pub fn resolve_args(&mut self, mut f: impl FnMut(FormatArg, bool) -> String) {
+        let mut next = 0;
+        let mut resolve = |arg: FormatArg, is_count: bool| match arg {
+            FormatArg::Next => {
+                next += 1;
+                f(FormatArg::Index(next - 1), is_count)
+            }
+            arg => f(arg, is_count),
+        };
+
+        for piece in &mut self.pieces {
+            let Piece::Placeholder { arg, spec } = piece else {
+                continue;
+            };
+
+            if let Some(spec) = spec {
+                for (start, end) in spec_params(spec).into_iter().rev() {
+                    if let Some(param) = FormatArg::parse(&spec[start..end]) {
+                        spec.replace_range(start..end, &resolve(param, true));
+                    }
+                }
+                if let Some(i) = spec.find(".*") {
+                    let param = resolve(FormatArg::Next, true);
+                    spec.replace_range(i..i + 2, &format!(".{param}$"));
+                }
+            }
+            if let Some(parsed) = FormatArg::parse(arg) {
+                *arg = resolve(parsed, false);
+            }
+        }
+    }
+
+    /// Replaces the placeholders with `{}` and returns them as standalone format strings.
+    pub fn take_placeholders(&mut self) -> Vec<String> {
+        let mut res = Vec::new();
+        for piece in &mut self.pieces {
+            if let Piece::Placeholder { arg, spec } = piece {
+                res.push(match spec {
+                    Some(spec) => format!("{{{arg}:{spec}}}"),
+                    None => format!("{{{arg}}}"),
+                });
+                *arg = String::new();
+                *spec = None;
+            }
+        }
+        res
+    }
+}
+
+impl Display for FormatString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => f.write_str(text)?,
+                Piece::Placeholder { arg, spec } => {
+                    f.write_char('{')?;
+                    f.write_str(arg)?;
+                    if let Some(spec) = spec {
+                        f.write_char(':')?;
+                        f.write_str(spec)?;
+                    }
+                    f.write_char('}')?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replaces field access placeholders, `{self.id}` or `{arg.0:?}`, with the names returned
+/// by `f(path)`, so the fields can be passed as named arguments.
+/// Returns `None` if the format string is malformed.
+pub fn capture_fields(fmt: &str, mut f: impl FnMut(&str) -> String) -> Option<String> {
+    let mut res = String::with_capacity(fmt.len());
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek() == Some(&c) => {
+                chars.next();
+                res.push(c);
+                res.push(c);
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        c => placeholder.push(c),
+                    }
+                }
+                let (arg, spec) = match placeholder.split_once(':') {
+                    Some((arg, spec)) => (arg, Some(spec)),
+                    None => (placeholder.as_str(), None),
+                };
+                res.push('{');
+                if is_field_path(arg.trim()) {
+                    res.push_str(&f(arg.trim()));
+                } else {
+                    res.push_str(arg);
+                }
+                if let Some(spec) = spec {
+                    res.push(':');
+                    res.push_str(spec);
+                }
+                res.push('}');
+            }
+            c => res.push(c),
+        }
+    }
+    Some(res)
+}
+
+/// Replaces the `{fn}` placeholders with the function name as text.
+/// Returns `None` if the format string is malformed.
+pub fn replace_fn_name(fmt: &str, name: &str) -> Option<String> {
+    let mut res = String::with_capacity(fmt.len());
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek() == Some(&c) => {
+                chars.next();
+                res.push(c);
+                res.push(c);
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        c => placeholder.push(c),
+                    }
+                }
+                if placeholder.trim() == "fn" {
+                    res.push_str(name);
+                } else {
+                    res.push('{');
+                    res.push_str(&placeholder);
+                    res.push('}');
+                }
+            }
+            c => res.push(c),
+        }
+    }
+    Some(res)
+}
+
+/// Translates the `${name}` placeholders of a template into `{name}`, `$$` into `$`.
+/// Other text, including `{}` placeholders, is kept as is.
+/// Returns `None` if a `${` placeholder is not closed.
+pub fn translate_template(template: &str) -> Option<String> {
+    let mut res = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('$', Some('$')) => {
+                chars.next();
+                res.push('$');
+            }
+            ('$', Some('{')) => {
+                chars.next();
+                res.push('{');
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        c => res.push(c),
+                    }
+                }
+                res.push('}');
+            }
+            (c, _) => res.push(c),
+        }
+    }
+    Some(res)
+}
+
+/// Checks that the argument is a field access, `ident.field` or `ident.0`.
+fn is_field_path(arg: &str) -> bool {
+    let mut segments = arg.split('.');
+    let is_ident = |s: &str| {
+        s.chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+    };
+    let is_index = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+
+    segments.next().is_some_and(is_ident)
+        && arg.contains('.')
+        && segments.all(|s| is_ident(s) || is_index(s))
+}
+
+/// Byte ranges of the parameters followed by `$` in the format spec.
+fn spec_params(spec: &str) -> Vec<(usize, usize)> {
+    spec.match_indices('$')
+        .filter_map(|(end, _)| {
+            let start = spec[..end]
+                .char_indices()
+                .rev()
+                .find(|(_, c)| !c.is_alphanumeric() && *c != '_')
+                .map_or(0, |(i, c)| i + c.len_utf8());
+            // Empty parameter is the `$` fill character, `{:$>5}`
+            (start < end).then_some((start, end))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let fmt = "text {{escaped}} {} {0} {name:>width$.prec$} {:.*} {:$>5}";
+        assert_eq!(FormatString::parse(fmt).unwrap().to_string(), fmt);
+    }
+
+    #[test]
+    fn resolve_args() {
+        let mut fmt = FormatString::parse("{{name}} {name:>width$.1$} {} {:.*} {0}").unwrap();
+        fmt.resolve_args(|arg, is_count| {
+            let name = match arg {
+                FormatArg::Index(i) => i.to_string(),
+                FormatArg::Name(name) => name,
+                FormatArg::Next => unreachable!(),
+            };
+            if is_count {
+                format!("c{name}")
+            } else {
+                format!("v{name}")
+            }
+        });
+        assert_eq!(
+            fmt.to_string(),
+            "{{name}} {vname:>cwidth$.c1$} {v0} {v2:.c1$} {v0}"
+        );
+    }
+
+    #[test]
+    fn take_placeholders() {
+        let mut fmt = FormatString::parse("{{a}} {a:>5} {}").unwrap();
+        assert_eq!(fmt.take_placeholders(), ["{a:>5}", "{}"]);
+        assert_eq!(fmt.to_string(), "{{a}} {} {}");
+    }
+
+    #[test]
+    fn capture_fields() {
+        let mut fields = Vec::new();
+        let fmt = super::capture_fields("{{a.b}} {self.id} {arg} {a.0:?} {}", |path| {
+            fields.push(path.to_owned());
+            format!("f{}", fields.len())
+        });
+        assert_eq!(fmt.as_deref(), Some("{{a.b}} {f1} {arg} {f2:?} {}"));
+        assert_eq!(fields, ["self.id", "a.0"]);
+        assert!(super::capture_fields("{a.b", |_| String::new()).is_none());
+    }
+
+    #[test]
+    fn escaped_braces() {
+        let mut fmt = FormatString::parse("{{{a}}} {{}} {{{{").unwrap();
+        assert_eq!(fmt.args().len(), 1);
+        assert_eq!(fmt.take_placeholders(), ["{a}"]);
+        assert_eq!(fmt.to_string(), "{{{}}} {{}} {{{{");
+
+        let fmt = super::capture_fields(r"{{{self.id}}} \{{self.id}}", |_| "f".to_owned());
+        assert_eq!(fmt.as_deref(), Some(r"{{{f}}} \{{self.id}}"));
+    }
+
+    #[test]
+    fn replace_fn_name() {
+        let fmt = super::replace_fn_name("{{fn}} {fn} {arg} { fn }", "load");
+        assert_eq!(fmt.as_deref(), Some("{{fn}} load {arg} load"));
+        assert!(super::replace_fn_name("{fn", "load").is_none());
+    }
+
+    #[test]
+    fn translate_template() {
+        let fmt = super::translate_template("${path} failed: {} ${arg:?} $$5 {:>width$} {{${a}}}");
+        assert_eq!(
+            fmt.as_deref(),
+            Some("{path} failed: {} {arg:?} $5 {:>width$} {{{a}}}")
+        );
+        assert_eq!(
+            super::translate_template("$$${a}$").as_deref(),
+            Some("${a}$")
+        );
+        assert!(super::translate_template("${path").is_none());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(FormatString::parse("{").is_none());
+        assert!(FormatString::parse("}").is_none());
+        assert!(FormatString::parse("{self.field}").is_none());
+    }
+}
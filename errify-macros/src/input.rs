@@ -1,36 +1,70 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
 use syn::{
+    braced, parenthesized,
     parse::{Parse, ParseStream},
+    parse_quote,
     punctuated::Punctuated,
-    Expr, ExprClosure, ImplItemFn, LitStr, Path, Token,
+    spanned::Spanned,
+    token, Expr, ExprClosure, ExprMacro, FieldPat, Ident, ImplItemFn, Item, LitInt, LitStr, Member,
+    Pat, PatStruct, Path, Token, Type,
+};
+
+use crate::{
+    format::{self, FormatArg, FormatString},
+    utils,
 };
 
 pub struct ErrifyMacroArgs {
+    opts: Options,
     cx: ImmediateContext,
 }
 
 impl Parse for ErrifyMacroArgs {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        Ok(Self { cx: input.parse()? })
+        let opts = input.parse::<Options>()?;
+        let cx = if opts.template && input.peek(LitStr) {
+            // The translated format string is parsed as usual along with the arguments
+            let lit = input.parse::<LitStr>()?;
+            let Some(fmt) = format::translate_template(&lit.value()) else {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    "Unclosed `${` placeholder in the template, use `$$` for a literal `$`",
+                ));
+            };
+            let lit = LitStr::new(&fmt, lit.span());
+            let rest = input.parse::<TokenStream>()?;
+            syn::parse2(quote! { #lit #rest })?
+        } else {
+            input.parse()?
+        };
+        Ok(Self { opts, cx })
     }
 }
 
 pub struct ErrifyWithMacroArgs {
+    opts: Options,
     cx: LazyContext,
 }
 
 impl Parse for ErrifyWithMacroArgs {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        Ok(Self { cx: input.parse()? })
+        Ok(Self {
+            opts: input.parse()?,
+            cx: input.parse()?,
+        })
     }
 }
 
 pub struct Args {
+    pub opts: Options,
     pub cx: Context,
 }
 
 impl From<ErrifyMacroArgs> for Args {
     fn from(value: ErrifyMacroArgs) -> Self {
         Self {
+            opts: value.opts,
             cx: value.cx.into(),
         }
     }
@@ -39,11 +73,307 @@ impl From<ErrifyMacroArgs> for Args {
 impl From<ErrifyWithMacroArgs> for Args {
     fn from(value: ErrifyWithMacroArgs) -> Self {
         Self {
+            opts: value.opts,
             cx: value.cx.into(),
         }
     }
 }
 
+/// Options preceding the context, each one followed by a comma.
+#[derive(Default)]
+pub struct Options {
+    /// Arguments captured by reference in the function body: `ref(a, b)`.
+    pub refs: Option<Punctuated<Ident, Token![,]>>,
+    /// Index of the `Result` element in a tuple return type: `field = 1`.
+    pub field: Option<LitInt>,
+    /// Move the error wrapping into a `#[cold]` function: `cold`.
+    pub cold: bool,
+    /// Function called with the context string on both success and failure: `hook = f`.
+    pub hook: Option<Expr>,
+    /// Error type constructed from the error and the context via `FromErrorContext`: `into = Type`.
+    pub into: Option<Type>,
+    /// Function called instead of `WrapErr::wrap_err` with the error and the context: `via = f`.
+    pub via: Option<Expr>,
+    /// Function called with the context string and whether the call succeeded: `telemetry = f`.
+    pub telemetry: Option<Expr>,
+    /// Level of the `tracing` event emitted on error: `tracing::Level::WARN`.
+    pub level: Option<Ident>,
+    /// Format arguments are captured as strings before the call,
+    /// the context is formatted only on error: `eager_args`.
+    pub eager_args: bool,
+    /// The format string is formatted only on error: `lazy`.
+    pub lazy: bool,
+    /// The context closure is called with a reference to the error: `with_err`.
+    pub with_err: bool,
+    /// The error is returned without wrapping if the context is empty: `skip_empty`.
+    pub skip_empty: bool,
+    /// Log the context with `defmt::error!` on error: `defmt`.
+    pub defmt: bool,
+    /// The name of the current thread is appended to the context on error: `thread`.
+    pub thread: bool,
+    /// A panic in the function body is caught and converted into the error: `ffi`.
+    pub ffi: bool,
+    /// The context provider is called on the first error only, its result is reused: `once_cx`.
+    pub once_cx: bool,
+    /// The arguments formatted with `Debug` are appended to the context: `args`.
+    pub dump_args: bool,
+    /// The error is wrapped only with `debug_assertions` enabled: `debug_only`.
+    pub debug_only: bool,
+    /// A panic while formatting the context is caught, a placeholder context is used instead: `no_panic`.
+    pub no_panic: bool,
+    /// Number of errors wrapped with the full context, later ones get a terse one: `rate_limit = 10`.
+    pub rate_limit: Option<LitInt>,
+    /// Name of the public function with the original body, without context: `expose_inner = f_raw`.
+    pub expose_inner: Option<Ident>,
+    /// `metrics` counter incremented on error with the context as a label: `counter = "db_errors"`.
+    pub counter: Option<LitStr>,
+    /// Function the formatted context is passed through on error: `format_with = f`.
+    pub format_with: Option<Expr>,
+    /// `tokio` task-local value prepended to the context on error: `task_local = REQUEST_ID`.
+    pub task_local: Option<Path>,
+    /// The error type is checked to implement `std::error::Error` at compile time: `assert_error`.
+    pub assert_error: bool,
+    /// Function the wrapped `Result` is passed through: `post = f`.
+    pub post: Option<Expr>,
+    /// Each error item of the returned `impl Stream` is wrapped: `stream`.
+    pub stream: bool,
+    /// Suggestion section attached to the `eyre::Report` along with the context: `section = "try X"`.
+    pub section: Option<Expr>,
+    /// Name of the wrapped function, the original one is kept as is: `rename = f_checked`.
+    pub rename: Option<Ident>,
+    /// `${name}` placeholders of the format string are translated into `{name}`: `template`.
+    pub template: bool,
+    /// Ok type of the return type for the `into` option: `ok = <Self as Trait>::Output`.
+    pub ok: Option<Type>,
+    /// Error type the error of the body is wrapped as: `err = Error`.
+    pub err: Option<Type>,
+    /// In debug builds, warns if the `anyhow::Error` already has many context layers: `warn_redundant`.
+    pub warn_redundant: bool,
+    /// The context is attached to the `anyhow::Error` as a note outside of the chain: `note`.
+    pub note: bool,
+    /// The context is passed through the transform set by `errify::set_context_transform`: `transform`.
+    pub transform: bool,
+}
+
+impl Parse for Options {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut opts = Self::default();
+
+        loop {
+            if input.peek(Token![ref]) && input.peek2(token::Paren) {
+                let kw = input.parse::<Token![ref]>()?;
+                let content;
+                parenthesized!(content in input);
+                let refs = content.parse_terminated(Ident::parse, Token![,])?;
+                set_once(&mut opts.refs, refs, kw.span, "ref")?;
+            } else if peek_key_value(input, "field") {
+                let (key, value) = parse_key_value::<LitInt>(input)?;
+                value.base10_parse::<usize>()?;
+                set_once(&mut opts.field, value, key.span(), "field")?;
+            } else if peek_key_value(input, "rate_limit") {
+                let (key, value) = parse_key_value::<LitInt>(input)?;
+                value.base10_parse::<usize>()?;
+                set_once(&mut opts.rate_limit, value, key.span(), "rate_limit")?;
+            } else if peek_key_value(input, "rename") {
+                let (key, value) = parse_key_value::<Ident>(input)?;
+                set_once(&mut opts.rename, value, key.span(), "rename")?;
+            } else if peek_key_value(input, "expose_inner") {
+                let (key, value) = parse_key_value::<Ident>(input)?;
+                set_once(&mut opts.expose_inner, value, key.span(), "expose_inner")?;
+            } else if peek_key_value(input, "counter") {
+                let (key, value) = parse_key_value::<LitStr>(input)?;
+                if cfg!(not(feature = "metrics")) {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "Counting errors requires the `metrics` feature",
+                    ));
+                }
+                set_once(&mut opts.counter, value, key.span(), "counter")?;
+            } else if peek_key_value(input, "format_with") {
+                let (key, value) = parse_key_value::<Expr>(input)?;
+                set_once(&mut opts.format_with, value, key.span(), "format_with")?;
+            } else if peek_key_value(input, "task_local") {
+                let (key, value) = parse_key_value::<Path>(input)?;
+                if cfg!(not(feature = "tokio")) {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "Reading a task-local requires the `tokio` feature",
+                    ));
+                }
+                set_once(&mut opts.task_local, value, key.span(), "task_local")?;
+            } else if peek_key_value(input, "post") {
+                let (key, value) = parse_key_value::<Expr>(input)?;
+                set_once(&mut opts.post, value, key.span(), "post")?;
+            } else if peek_key_value(input, "section") {
+                let (key, value) = parse_key_value::<Expr>(input)?;
+                if cfg!(not(feature = "color-eyre")) {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "Attaching a section requires the `color-eyre` feature",
+                    ));
+                }
+                set_once(&mut opts.section, value, key.span(), "section")?;
+            } else if peek_key_value(input, "hook") {
+                let (key, value) = parse_key_value::<Expr>(input)?;
+                set_once(&mut opts.hook, value, key.span(), "hook")?;
+            } else if peek_key_value(input, "ok") {
+                let (key, value) = parse_key_value::<Type>(input)?;
+                set_once(&mut opts.ok, value, key.span(), "ok")?;
+            } else if peek_key_value(input, "err") {
+                let (key, value) = parse_key_value::<Type>(input)?;
+                set_once(&mut opts.err, value, key.span(), "err")?;
+            } else if peek_key_value(input, "into") {
+                let (key, value) = parse_key_value::<Type>(input)?;
+                set_once(&mut opts.into, value, key.span(), "into")?;
+            } else if peek_key_value(input, "via") {
+                let (key, value) = parse_key_value::<Expr>(input)?;
+                set_once(&mut opts.via, value, key.span(), "via")?;
+            } else if peek_key_value(input, "telemetry") {
+                let (key, value) = parse_key_value::<Expr>(input)?;
+                set_once(&mut opts.telemetry, value, key.span(), "telemetry")?;
+            } else if peek_level(input) {
+                let path = input.parse::<Path>()?;
+                let level = path.segments.last().unwrap().ident.clone();
+                if cfg!(not(feature = "tracing")) {
+                    return Err(syn::Error::new_spanned(
+                        path,
+                        "Logging on error requires the `tracing` feature",
+                    ));
+                }
+                set_once(&mut opts.level, level, path.span(), "tracing level")?;
+            } else if peek_flag(input, "cold") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.cold, key.span(), "cold")?;
+            } else if peek_flag(input, "eager_args") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.eager_args, key.span(), "eager_args")?;
+            } else if peek_flag(input, "lazy") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.lazy, key.span(), "lazy")?;
+            } else if peek_flag(input, "with_err") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.with_err, key.span(), "with_err")?;
+            } else if peek_flag(input, "skip_empty") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.skip_empty, key.span(), "skip_empty")?;
+            } else if peek_flag(input, "thread") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.thread, key.span(), "thread")?;
+            } else if peek_flag(input, "ffi") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.ffi, key.span(), "ffi")?;
+            } else if peek_flag(input, "once_cx") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.once_cx, key.span(), "once_cx")?;
+            } else if peek_flag(input, "args") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.dump_args, key.span(), "args")?;
+            } else if peek_flag(input, "assert_error") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.assert_error, key.span(), "assert_error")?;
+            } else if peek_flag(input, "stream") {
+                let key = input.parse::<Ident>()?;
+                if cfg!(not(feature = "stream")) {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "Wrapping stream items requires the `stream` feature",
+                    ));
+                }
+                set_flag(&mut opts.stream, key.span(), "stream")?;
+            } else if peek_flag(input, "template") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.template, key.span(), "template")?;
+            } else if peek_flag(input, "note") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.note, key.span(), "note")?;
+            } else if peek_flag(input, "debug_only") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.debug_only, key.span(), "debug_only")?;
+            } else if peek_flag(input, "no_panic") {
+                let key = input.parse::<Ident>()?;
+                set_flag(&mut opts.no_panic, key.span(), "no_panic")?;
+            } else if peek_flag(input, "warn_redundant") {
+                let key = input.parse::<Ident>()?;
+                if cfg!(not(feature = "anyhow")) {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "Warning about redundant context requires the `anyhow` feature",
+                    ));
+                }
+                set_flag(&mut opts.warn_redundant, key.span(), "warn_redundant")?;
+            } else if peek_flag(input, "transform") {
+                let key = input.parse::<Ident>()?;
+                if cfg!(not(feature = "context-transform")) {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "Transforming the context requires the `context-transform` feature",
+                    ));
+                }
+                set_flag(&mut opts.transform, key.span(), "transform")?;
+            } else if peek_flag(input, "defmt") {
+                let key = input.parse::<Ident>()?;
+                if cfg!(not(feature = "defmt")) {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "Logging on error with `defmt` requires the `defmt` feature",
+                    ));
+                }
+                set_flag(&mut opts.defmt, key.span(), "defmt")?;
+            } else {
+                break;
+            }
+
+            input.parse::<Token![,]>()?;
+        }
+
+        Ok(opts)
+    }
+}
+
+/// Peeks `tracing::Level::$LEVEL` or `Level::$LEVEL` followed by a comma.
+fn peek_level(input: ParseStream) -> bool {
+    let fork = input.fork();
+    let Ok(path) = fork.parse::<Path>() else {
+        return false;
+    };
+    let mut segments = path.segments.iter().rev();
+    let level = segments.next().map(|s| s.ident.to_string());
+    let ty = segments.next().map(|s| s.ident.to_string());
+
+    matches!(
+        level.as_deref(),
+        Some("TRACE" | "DEBUG" | "INFO" | "WARN" | "ERROR")
+    ) && ty.as_deref() == Some("Level")
+        && fork.peek(Token![,])
+}
+
+fn peek_flag(input: ParseStream, key: &str) -> bool {
+    let fork = input.fork();
+    matches!(fork.parse::<Ident>(), Ok(ident) if ident == key) && fork.peek(Token![,])
+}
+
+fn peek_key_value(input: ParseStream, key: &str) -> bool {
+    let fork = input.fork();
+    matches!(fork.parse::<Ident>(), Ok(ident) if ident == key)
+        && fork.peek(Token![=])
+        && !fork.peek(Token![==])
+}
+
+fn parse_key_value<T: Parse>(input: ParseStream) -> syn::Result<(Ident, T)> {
+    let key = input.parse::<Ident>()?;
+    input.parse::<Token![=]>()?;
+    let value = input.parse::<T>()?;
+    Ok((key, value))
+}
+
+fn set_once<T>(slot: &mut Option<T>, value: T, span: Span, name: &str) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(syn::Error::new(span, format!("Duplicate `{name}` option")));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
 pub enum Context {
     Immediate(ImmediateContext),
     Lazy(LazyContext),
@@ -66,37 +396,341 @@ pub enum ImmediateContext {
         lit: LitStr,
         args: Punctuated<Expr, Token![,]>,
     },
+    /// Format string produced by a macro, e.g. `concat!(...)`.
+    Macro {
+        mac: ExprMacro,
+        args: Punctuated<Expr, Token![,]>,
+    },
     Expr {
         expr: Expr,
     },
+    /// Argument formatted with `Display` only on error: `ctx_arg = arg`.
+    Arg {
+        ident: Ident,
+    },
+    /// Context with a code for CLI tools: `cli(code = "E042", "fmt", args...)`.
+    Cli {
+        code: LitStr,
+        lit: LitStr,
+        args: Punctuated<Expr, Token![,]>,
+    },
+    /// Context set to the field of the matching error variant: `Error::Io { context } => "..."`.
+    Variant {
+        path: Path,
+        field: Ident,
+        lit: LitStr,
+        args: Punctuated<Expr, Token![,]>,
+    },
+    /// Context provided by the error via `errify::ErrorContext`: `from_error`.
+    FromError,
+    /// Context selected by downcasting the error: `match_source { Io => "...", _ => "..." }`.
+    MatchSource {
+        arms: Vec<(Type, LitStr)>,
+        default: Option<LitStr>,
+    },
 }
 
 impl Parse for ImmediateContext {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let res = if input.peek(LitStr) {
-            let lit = input.parse()?;
-            let comma = input.parse::<Option<Token![,]>>()?;
-            let args = if comma.is_some() {
-                input.parse_terminated(Expr::parse, Token![,])?
-            } else {
-                Default::default()
-            };
+            let (lit, args) = parse_format_args(input)?;
 
             Self::Literal { lit, args }
+        } else if peek_key_value(input, "ctx_arg") {
+            let (_, ident) = parse_key_value::<Ident>(input)?;
+            Self::Arg { ident }
+        } else if peek_from_error(input) {
+            input.parse::<Ident>()?;
+            Self::FromError
+        } else if peek_cli(input) {
+            parse_cli(input)?
+        } else if peek_match_source(input) {
+            parse_match_source(input)?
+        } else if peek_variant(input) {
+            parse_variant(input)?
         } else {
-            Self::Expr {
-                expr: input.parse()?,
+            match input.parse()? {
+                Expr::Macro(mac) if input.peek(Token![,]) => {
+                    input.parse::<Token![,]>()?;
+                    Self::Macro {
+                        mac,
+                        args: input.parse_terminated(Expr::parse, Token![,])?,
+                    }
+                }
+                // `format!` requires a literal, a `const` can only be the whole context
+                expr if input.peek(Token![,]) => {
+                    return Err(syn::Error::new_spanned(
+                        expr,
+                        "Format arguments require a string literal as the format string, \
+                         a constant can only be used as the whole context without arguments",
+                    ))
+                }
+                expr => Self::Expr { expr },
             }
         };
 
         if !input.is_empty() {
             return Err(syn::Error::new(input.span(), "Unexpected tokens"));
         }
+        check_try(&res)?;
 
         Ok(res)
     }
 }
 
+/// Reports the `?` operator in the context, it would return from the function before the body runs.
+fn check_try(cx: &ImmediateContext) -> syn::Result<()> {
+    let exprs: Vec<&Expr> = match cx {
+        ImmediateContext::Literal { args, .. }
+        | ImmediateContext::Macro { args, .. }
+        | ImmediateContext::Cli { args, .. }
+        | ImmediateContext::Variant { args, .. } => args.iter().collect(),
+        ImmediateContext::Expr { expr } => vec![expr],
+        ImmediateContext::Arg { .. }
+        | ImmediateContext::FromError
+        | ImmediateContext::MatchSource { .. } => Vec::new(),
+    };
+
+    match exprs.into_iter().find_map(utils::find_try) {
+        Some(expr) => Err(syn::Error::new_spanned(
+            expr.question_token,
+            "The `?` operator is not supported in the context, it would return from the function \
+             before its body runs without context. Handle the error in the context expression",
+        )),
+        None => Ok(()),
+    }
+}
+
+fn peek_from_error(input: ParseStream) -> bool {
+    let fork = input.fork();
+    matches!(fork.parse::<Ident>(), Ok(ident) if ident == "from_error") && fork.is_empty()
+}
+
+fn peek_cli(input: ParseStream) -> bool {
+    let fork = input.fork();
+    matches!(fork.parse::<Ident>(), Ok(ident) if ident == "cli") && fork.peek(token::Paren)
+}
+
+fn parse_cli(input: ParseStream) -> syn::Result<ImmediateContext> {
+    input.parse::<Ident>()?;
+    let content;
+    parenthesized!(content in input);
+
+    if !peek_key_value(&content, "code") {
+        return Err(syn::Error::new(
+            content.span(),
+            "Expected `code = \"...\"` as the first `cli` argument",
+        ));
+    }
+    let (_, code) = parse_key_value::<LitStr>(&content)?;
+    content.parse::<Token![,]>()?;
+
+    let (lit, args) = parse_format_args(&content)?;
+
+    Ok(ImmediateContext::Cli { code, lit, args })
+}
+
+fn peek_match_source(input: ParseStream) -> bool {
+    let fork = input.fork();
+    matches!(fork.parse::<Ident>(), Ok(ident) if ident == "match_source") && fork.peek(token::Brace)
+}
+
+fn parse_match_source(input: ParseStream) -> syn::Result<ImmediateContext> {
+    let key = input.parse::<Ident>()?;
+    let content;
+    braced!(content in input);
+
+    let mut arms = Vec::new();
+    let mut default = None;
+    while !content.is_empty() {
+        if default.is_some() {
+            return Err(syn::Error::new(
+                content.span(),
+                "Unreachable arm after the `_` arm",
+            ));
+        }
+        if content.peek(Token![_]) {
+            content.parse::<Token![_]>()?;
+            content.parse::<Token![=>]>()?;
+            default = Some(content.parse::<LitStr>()?);
+        } else {
+            let ty = content.parse::<Type>()?;
+            content.parse::<Token![=>]>()?;
+            arms.push((ty, content.parse::<LitStr>()?));
+        }
+        if content.is_empty() {
+            break;
+        }
+        content.parse::<Token![,]>()?;
+    }
+
+    if arms.is_empty() {
+        return Err(syn::Error::new(
+            key.span(),
+            "`match_source` requires at least one error type arm",
+        ));
+    }
+
+    Ok(ImmediateContext::MatchSource { arms, default })
+}
+
+fn peek_variant(input: ParseStream) -> bool {
+    let fork = input.fork();
+    matches!(Pat::parse_single(&fork), Ok(Pat::Struct(_))) && fork.peek(Token![=>])
+}
+
+fn parse_variant(input: ParseStream) -> syn::Result<ImmediateContext> {
+    let pat = Pat::parse_single(input)?;
+    let span = pat.span();
+    let field = match &pat {
+        Pat::Struct(PatStruct {
+            qself: None,
+            fields,
+            rest: None,
+            ..
+        }) if fields.len() == 1 => match &fields[0] {
+            FieldPat {
+                member: Member::Named(field),
+                colon_token: None,
+                ..
+            } => Some(field.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+    let (Pat::Struct(PatStruct { path, .. }), Some(field)) = (pat, field) else {
+        return Err(syn::Error::new(
+            span,
+            "Expected a struct variant pattern with the field to set, e.g. `Error::Io { context }`",
+        ));
+    };
+    input.parse::<Token![=>]>()?;
+
+    let (lit, args) = parse_format_args(input)?;
+
+    Ok(ImmediateContext::Variant {
+        path,
+        field,
+        lit,
+        args,
+    })
+}
+
+/// Parses the format string with arguments, `"fmt", args...`.
+///
+/// Field access placeholders, `{self.id}`, are replaced with generated named arguments.
+fn parse_format_args(input: ParseStream) -> syn::Result<(LitStr, Punctuated<Expr, Token![,]>)> {
+    let lit = input.parse::<LitStr>()?;
+    let comma = input.parse::<Option<Token![,]>>()?;
+    let mut args = if comma.is_some() {
+        input.parse_terminated(Expr::parse, Token![,])?
+    } else {
+        Punctuated::new()
+    };
+
+    let mut fields = Vec::new();
+    // No leading underscores, `eager_args` prefixes the names with `__errify_arg_`
+    let fmt = format::capture_fields(&lit.value(), |path| {
+        fields.push(path.to_owned());
+        format!("errify_field_{}", fields.len() - 1)
+    });
+    let lit = match fmt {
+        Some(fmt) if !fields.is_empty() => {
+            for (i, path) in fields.iter().enumerate() {
+                let name = format_ident!("errify_field_{}", i, span = lit.span());
+                let field = syn::parse_str::<TokenStream>(path)?
+                    .into_iter()
+                    .map(|mut tt| {
+                        tt.set_span(lit.span());
+                        tt
+                    })
+                    .collect::<TokenStream>();
+                args.push(parse_quote!(#name = #field));
+            }
+            LitStr::new(&fmt, lit.span())
+        }
+        _ => lit,
+    };
+    check_unused_args(&lit, &args)?;
+
+    Ok((lit, args))
+}
+
+/// Splits the named format argument `name = expr`.
+pub fn named_arg(arg: &Expr) -> Option<(&Ident, &Expr)> {
+    let Expr::Assign(assign) = arg else {
+        return None;
+    };
+    let Expr::Path(path) = &*assign.left else {
+        return None;
+    };
+    Some((path.path.get_ident()?, &assign.right))
+}
+
+/// Reports arguments that are not referenced by the format string.
+fn check_unused_args(lit: &LitStr, args: &Punctuated<Expr, Token![,]>) -> syn::Result<()> {
+    let Some(fmt) = FormatString::parse(&lit.value()) else {
+        // Malformed format string, `format!` reports it
+        return Ok(());
+    };
+
+    let mut positional = Vec::new();
+    let mut named = Vec::new();
+    for arg in args {
+        match named_arg(arg) {
+            Some((name, _)) => named.push((name.to_string(), arg, false)),
+            None => positional.push((arg, false)),
+        }
+    }
+
+    let mut next = 0;
+    for arg in fmt.args() {
+        match arg {
+            FormatArg::Next => {
+                if let Some((_, used)) = positional.get_mut(next) {
+                    *used = true;
+                }
+                next += 1;
+            }
+            FormatArg::Index(index) => {
+                if let Some((_, used)) = positional.get_mut(index) {
+                    *used = true;
+                }
+            }
+            FormatArg::Name(name) => {
+                if let Some((_, _, used)) = named.iter_mut().find(|(n, _, _)| *n == name) {
+                    *used = true;
+                }
+            }
+        }
+    }
+
+    let unused = positional
+        .iter()
+        .filter(|(_, used)| !used)
+        .map(|(arg, _)| {
+            (
+                *arg,
+                "Positional argument is never used in the format string",
+            )
+        })
+        .chain(
+            named
+                .iter()
+                .filter(|(_, _, used)| !used)
+                .map(|(_, arg, _)| (*arg, "Named argument is never used in the format string")),
+        )
+        .map(|(arg, msg)| syn::Error::new_spanned(arg, msg))
+        .reduce(|mut acc, err| {
+            acc.combine(err);
+            acc
+        });
+    match unused {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
 pub enum LazyContext {
     Closure { def: ExprClosure },
     Function { path: Path },
@@ -128,8 +762,24 @@ pub struct Input {
 
 impl Parse for Input {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        Ok(Self {
-            func: input.parse()?,
-        })
+        let fork = input.fork();
+        match input.parse() {
+            Ok(func) => Ok(Self { func }),
+            Err(err) => match fork.parse::<Item>() {
+                Ok(Item::Fn(_)) | Err(_) => Err(err),
+                Ok(item) => Err(syn::Error::new_spanned(
+                    item,
+                    "The macro only supports functions and methods",
+                )),
+            },
+        }
+    }
+}
+
+fn set_flag(flag: &mut bool, span: Span, name: &str) -> syn::Result<()> {
+    if *flag {
+        return Err(syn::Error::new(span, format!("Duplicate `{name}` option")));
     }
+    *flag = true;
+    Ok(())
 }
@@ -1,10 +1,16 @@
 mod errify_macro;
+mod format;
 mod input;
+mod module_context;
 mod output;
+mod utils;
 
 use proc_macro::TokenStream;
 
-use crate::errify_macro::{errify_impl, errify_with_impl};
+use crate::{
+    errify_macro::{errify_impl, errify_with_impl},
+    module_context::module_context_impl,
+};
 
 /// Macro that provides error context on entire function.
 /// Supports `async` functions.
@@ -13,9 +19,140 @@ use crate::errify_macro::{errify_impl, errify_with_impl};
 ///
 /// # Syntax
 /// ```text
-/// #[errify( $( $fmt:literal $(, $arg:expr)* ) | $expr:expr )]
+/// #[errify( $( $option , )* $( $fmt:literal $(, $arg:expr)* ) | $( $fmt:macro , $($arg:expr),+ ) | $expr:expr | ctx_arg = $arg:ident | from_error | $match_source | $cli | $variant )]
+///
+/// $match_source = match_source { $( $err:ty => $fmt:literal ),+ $(, _ => $fmt:literal )? }
+/// $variant = $path:path { $field:ident } => $fmt:literal $(, $arg:expr)*
+/// $cli = cli( code = $code:literal , $fmt:literal $(, $arg:expr)* )
 /// ```
 ///
+/// Format arguments require `$fmt` to be a string literal, as in `format!`. An expression,
+/// e.g. a `const` message, is used as the whole context as is, without interpolation.
+///
+/// `ctx_arg = $arg` formats the function argument `$arg` with `Display` as the context only on error,
+/// the argument is captured by reference in the function body, as with the `ref` option.
+///
+/// `from_error` uses the context returned by `errify::ErrorContext::default_context` of the error,
+/// so the error type provides its own context. It is not supported with the `hook` and `telemetry` options.
+///
+/// `match_source` selects the context by downcasting the error with `downcast_ref`
+/// (e.g. `anyhow::Error`, `eyre::Report`), an error without a matching arm is returned as is.
+/// It is not supported with the `hook` and `telemetry` options.
+///
+/// `cli` creates the `errify::CliContext` with a short code
+/// and the formatted message, which can be retrieved by downcasting the error to it.
+///
+/// `$variant` sets the field of the matching error variant to the formatted context with `Into`
+/// (e.g. `String` or `Option<String>`), other variants are returned as is.
+/// The error type doesn't need to implement `WrapErr`. Not supported with the `cold`, `via`
+/// and `into` options.
+///
+/// # Options
+/// The options are shared with [`errify_with`](macro@errify_with), unless noted otherwise.
+///
+/// - `ref($($arg:ident),*)`: listed arguments are captured by reference in the function body instead of being moved.
+/// - `field = $index:literal`: the function returns a tuple, and the `Result` to wrap is its element `$index`.
+/// - `rename = $name:ident`: the wrapped function is emitted as `$name`, and the original function
+///   is kept as is, e.g. to migrate callers gradually. Not supported with the `expose_inner` option.
+/// - `expose_inner = $name:ident`: the original function is also emitted as `pub fn $name`,
+///   without the context and other options, e.g. to test the logic without context.
+///   Not supported in trait impls, which can't have extra methods.
+/// - `cold`: the error wrapping is moved into a separate `#[cold]` and `#[inline(never)]` function
+///   `__errify_cold`, called on error with the error and a closure building the context.
+///   Moved into it: the `wrap_err_with` call and everything the closure does on error, i.e. the
+///   formatting of a context deferred to the error (a format string referring only to copied
+///   arguments, see `eager_args`, or with the `lazy` option), the concatenation of the `eager_args`
+///   placeholders and the context added by other options, e.g. `thread`.
+///   Kept inline: the formatting of other format strings, the `eager_args` placeholders and
+///   expression contexts, evaluated before the call, only the result is moved into the closure.
+/// - `hook = $f:expr`: `$f(&str)` is called with the context regardless of the function outcome.
+/// - `post = $f:expr`: the `Result` is passed through `$f(Result<T, E>) -> Result<T, E>` after
+///   the error is wrapped, e.g. to turn certain errors into a fallback value.
+/// - `into = $ty:ty`: the error is converted into `$ty` with the context via `errify::FromErrorContext`,
+///   the function returns `$ty` as the error type instead of the one in the signature.
+/// - `err = $ty:ty`: the error of the body is checked to be `$ty` and wrapped as `$ty`, e.g. an error
+///   type alias selected with `cfg` for each platform.
+/// - `ok = $ty:ty`: the ok type of the function returning the `into` error type, for return types
+///   without a visible error type, e.g. an associated type `<Self as Trait>::Output`.
+///   Requires the `into` option.
+/// - `via = $f:expr`: `$f(err, context)` is called to wrap the error instead of `WrapErr::wrap_err`,
+///   e.g. a method of a custom error trait bounding a generic error type. Not supported with the `cold` option.
+/// - `section = $expr`: the `eyre::Report` is wrapped with the context and the `$expr` suggestion section
+///   via `errify::WrapErrSection`, shown by the `color-eyre` handler. Not supported with the `cold`,
+///   `via` and `into` options. Requires the `color-eyre` feature.
+/// - `note`: the context is attached to the `anyhow::Error` as a note via `errify::WrapErrNote`,
+///   notes of nested calls are merged into one layer of the chain and retrieved by downcasting
+///   the error to `errify::Notes`, the wrapped error stays downcastable. Not supported with the `cold`, `via`, `into` and `section` options.
+/// - `telemetry = $f:expr`: `$f(&str, bool)` is called with the context and whether the function succeeded.
+/// - `tracing::Level::$LEVEL`: on error, a `tracing` event is emitted at the level with the context and the error,
+///   the error type must implement `Display`. Requires the `tracing` feature.
+/// - `counter = $name:literal`: on error, the `metrics` counter `$name` is incremented with the context
+///   as the `context` label. Requires the `metrics` feature.
+/// - `warn_redundant`: in debug builds, on error, a warning is reported if the `anyhow::Error` already has
+///   more than `errify::REDUNDANT_CONTEXT_DEPTH` errors in its chain above the root cause (context layers
+///   and source errors), to find redundant annotations. The warning is passed to the handler set by
+///   `errify::set_redundant_context_handler`, by default it's emitted as a `tracing` event with the
+///   `tracing` feature or printed to stderr.
+///   Requires the `anyhow` feature.
+/// - `defmt`: on error, the context is logged with `defmt::error!`, the context must implement
+///   `defmt::Format` (e.g. `&str` or `String`). Requires the `defmt` feature and the `defmt` dependency.
+/// - `eager_args` (format string contexts only): each placeholder of the format string is formatted into a `String` before the call,
+///   but the context is assembled only on error. Values are captured before the function body runs,
+///   so mutations in the body are not reflected, while only the final concatenation is deferred.
+///   By default the whole context is formatted before the call instead. If every argument of
+///   the format string is an argument of a primitive `Copy` type (e.g. `i32`) or its field, or a shared
+///   reference to a value without interior mutability (`&str`, `&[u8]`, `&Path`, `&OsStr`),
+///   the argument is copied before the call and the context is formatted from the copy on error only.
+///   References are formatted before the call in functions returning futures or streams.
+/// - `lazy` (format string contexts only): the format string is formatted only on error, as in `errify_with`. The arguments are
+///   borrowed until the error is wrapped, so use the `ref` option for arguments moved in the body.
+///   Unlike the default, calls and values changed by the body (e.g. a `Cell` or a static counter)
+///   are evaluated after the call, use it to opt in to formatting any context on error only.
+/// - `template` (format string contexts only): `${name}` placeholders of the format string are translated into `{name}`
+///   and `$$` into `$`, e.g. `#[errify(template, "GET ${path} failed")]` to share templates with
+///   logging. Regular `{}` placeholders can be mixed in.
+/// - `skip_empty`: on error, the context is formatted into a `String`,
+///   and the error is returned without wrapping if it is empty.
+/// - `rate_limit = $n:literal`: only the first `$n` errors of the function in the process are wrapped
+///   with the full context, later ones with the terse `func (context rate limited)` context.
+///   The full context is still evaluated for them if it's evaluated before the call, i.e. eager
+///   format strings and expression contexts, only deferred contexts are skipped.
+/// - `thread`: on error, the name of the current thread (or its id for unnamed threads)
+///   is appended to the context, e.g. `context [thread worker-1]`.
+/// - `task_local = $key:path`: on error, the value of the `tokio` task-local `$key` is prepended
+///   to the context, e.g. `[req-42] context`, or nothing if it is not set for the current task.
+///   Requires the `tokio` feature.
+/// - `format_with = $f:expr`: on error, the context formatted into a `String` is passed through
+///   `$f(&str) -> String` before wrapping, e.g. to add ANSI colors for CLI output.
+/// - `transform`: on error, the context formatted into a `String` is passed through the transform
+///   set by `errify::set_context_transform`, e.g. to redact it. Requires the `context-transform` feature.
+/// - `ffi`: a panic in the function body is caught and converted into the error with
+///   `From<errify::PanicError>`, then wrapped with the context. A panic while wrapping the error,
+///   e.g. in `Display` of the context, a hook or `WrapErr`, is caught too and converted into
+///   the error without context, so no unwinding escapes an `extern "C"` function.
+///   Not supported with `async` functions and the `field` option.
+/// - `once_cx`: the context provider (e.g. `|| expensive()`) is called on the first error only, and its result formatted
+///   into a `String` is reused by every later call of the function, e.g. in a retry loop.
+///   The context is computed once per process, so it can't refer to the arguments or `self`.
+/// - `args`: the arguments formatted with `Debug` are appended to the context,
+///   e.g. `context (a = 1, b = "x")`. The arguments are formatted before the call,
+///   the receiver and destructuring patterns are skipped.
+/// - `stream`: the function returns `impl Stream<Item = Result<T, E>>` (e.g. built with `async-stream`),
+///   and each error item is wrapped with the context, which is cloned for every error.
+///   Not supported with `async` functions and the `hook`, `telemetry`, `ffi` and `into` options.
+///   Requires the `stream` feature.
+/// - `assert_error`: the error type is checked to implement `std::error::Error` at compile time,
+///   not only `WrapErr`, with a diagnostic pointing at the error type.
+/// - `debug_only`: the error is wrapped only if `debug_assertions` are enabled, otherwise
+///   the function body is called as is, without the context and other options.
+///   Not supported with the `into` option, which changes the return type.
+/// - `no_panic`: a panic while evaluating or formatting the context (e.g. in a `Display` impl)
+///   is caught and the `<context formatting panicked>` context is used instead, so the wrapping
+///   never panics, e.g. in cleanup functions called from `Drop`. Not supported with the `eager_args` option.
+/// - `with_err` (expression contexts only): the context is a closure called with a reference to the error,
+///   e.g. `|err| Context::new(arg, err)`. The closure is created before the call, like any
+///   other expression context, and called only on error. Not supported with the `hook` and `telemetry` options.
+///
 /// # Usage example
 ///
 /// ### Format string with arguments
@@ -37,11 +174,54 @@ use crate::errify_macro::{errify_impl, errify_with_impl};
 ///     // ...
 /// }
 /// ```
+///
+/// ### Format string from macro
+/// ```ignore
+/// use errify::errify;
+///
+/// #[errify(concat!("Custom error context, version ", env!("CARGO_PKG_VERSION"), ", arg = {}"), arg)]
+/// fn func(arg: i32) -> Result<(), CustomError> {
+///     // ...
+/// }
+/// ```
+///
+/// ### Context by error source
+/// ```ignore
+/// use errify::errify;
+///
+/// #[errify(match_source {
+///     std::io::Error => "Failed to read {path}",
+///     std::num::ParseIntError => "Failed to parse {path}",
+/// })]
+/// fn func(path: &str) -> Result<i32, anyhow::Error> {
+///     // ...
+/// }
+/// ```
+///
+/// ### Context in the error variant field
+/// ```ignore
+/// use errify::errify;
+///
+/// #[errify(Error::Io { context } => "Failed to read {path}")]
+/// fn func(path: &str) -> Result<String, Error> {
+///     // ...
+/// }
+/// ```
+///
+/// ### Capture by reference
+/// ```ignore
+/// use errify::errify;
+///
+/// #[errify(ref(big), "Custom error context, big len = {}", big.len())]
+/// fn func(big: Vec<u8>) -> Result<(), CustomError> {
+///     // `big` is `&Vec<u8>` here
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn errify(args: TokenStream, input: TokenStream) -> TokenStream {
     match errify_impl(args.into(), input.into()) {
         Ok(tokens) => tokens.into(),
-        Err(diag) => diag.emit_as_expr_tokens().into(),
+        Err(diag) => diag.emit_as_item_tokens().into(),
     }
 }
 
@@ -52,9 +232,14 @@ pub fn errify(args: TokenStream, input: TokenStream) -> TokenStream {
 ///
 /// # Syntax
 /// ```text
-/// #[errify_with( $closure:expr | $func:ident )]
+/// #[errify_with( $( $option , )* $closure:expr | $func:ident )]
 /// ```
 ///
+/// # Options
+/// The options are the same as the [options of `errify`](macro@errify#options), except the ones
+/// for format string contexts (`eager_args`, `lazy` and `template`) and expression contexts (`with_err`).
+/// With the `cold` option the context closure is called in the cold function `__errify_cold`.
+///
 /// # Usage example
 ///
 /// ### Closure
@@ -82,6 +267,42 @@ pub fn errify(args: TokenStream, input: TokenStream) -> TokenStream {
 pub fn errify_with(args: TokenStream, input: TokenStream) -> TokenStream {
     match errify_with_impl(args.into(), input.into()) {
         Ok(tokens) => tokens.into(),
-        Err(diag) => diag.emit_as_expr_tokens().into(),
+        Err(diag) => diag.emit_as_item_tokens().into(),
+    }
+}
+
+/// Macro that applies the `errify` macro with a uniform context to every function of the module
+/// returning `Result<T, E>` (or an alias named `Result`, e.g. `io::Result<T>`).
+///
+/// The `{fn}` placeholder of the format string is replaced with the name of each function,
+/// without the `r#` prefix of raw identifiers. The functions of the block, the methods of its
+/// impl blocks and the functions of its inline modules are wrapped. The functions with their own
+/// `errify` or `errify_with` attribute are kept as is, as well as default methods of traits
+/// and functions generated by macros.
+///
+/// # Syntax
+/// ```text
+/// module_context!( $fmt:literal , { $( $item:item )* } )
+/// ```
+///
+/// # Usage example
+/// ```ignore
+/// use errify::module_context;
+///
+/// module_context!("Failed to {fn}", {
+///     fn load(path: &str) -> Result<String, CustomError> {
+///         // ...
+///     }
+///
+///     fn save(path: &str) -> Result<(), CustomError> {
+///         // ...
+///     }
+/// });
+/// ```
+#[proc_macro]
+pub fn module_context(input: TokenStream) -> TokenStream {
+    match module_context_impl(input.into()) {
+        Ok(tokens) => tokens.into(),
+        Err(diag) => diag.emit_as_item_tokens().into(),
     }
 }
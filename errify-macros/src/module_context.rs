@@ -0,0 +1,109 @@
+use proc_macro2::TokenStream;
+use proc_macro2_diagnostics::Diagnostic;
+use quote::{quote, ToTokens};
+use syn::{
+    braced,
+    ext::IdentExt,
+    parse::{Parse, ParseStream},
+    parse_quote, Attribute, ImplItem, Item, LitStr, ReturnType, Signature, Token, Type,
+};
+
+use crate::format;
+
+pub fn module_context_impl(input: TokenStream) -> Result<TokenStream, Diagnostic> {
+    let m = syn::parse2::<ModuleContext>(input)?;
+    let res = quote! { #m };
+
+    Ok(res)
+}
+
+/// `module_context!("context {fn}", { items })`
+struct ModuleContext {
+    items: Vec<Item>,
+}
+
+impl Parse for ModuleContext {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lit = input.parse::<LitStr>()?;
+        input.parse::<Token![,]>()?;
+        let content;
+        braced!(content in input);
+        if !input.is_empty() {
+            return Err(syn::Error::new(input.span(), "Unexpected tokens"));
+        }
+
+        let mut items = Vec::new();
+        while !content.is_empty() {
+            let mut item = content.parse::<Item>()?;
+            apply_context(&mut item, &lit)?;
+            items.push(item);
+        }
+
+        Ok(Self { items })
+    }
+}
+
+/// Adds the context to the function, the methods of the impl block or the items of the inline module.
+/// Other items, e.g. default methods of traits or functions in macro invocations, are kept as is.
+fn apply_context(item: &mut Item, lit: &LitStr) -> syn::Result<()> {
+    match item {
+        Item::Fn(func) => apply_fn_context(&mut func.attrs, &func.sig, lit),
+        Item::Impl(imp) => imp.items.iter_mut().try_for_each(|item| match item {
+            ImplItem::Fn(func) => apply_fn_context(&mut func.attrs, &func.sig, lit),
+            _ => Ok(()),
+        }),
+        Item::Mod(module) => match &mut module.content {
+            Some((_, items)) => items
+                .iter_mut()
+                .try_for_each(|item| apply_context(item, lit)),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn apply_fn_context(attrs: &mut Vec<Attribute>, sig: &Signature, lit: &LitStr) -> syn::Result<()> {
+    if !returns_result(sig) || has_errify(attrs) {
+        return Ok(());
+    }
+    let name = sig.ident.unraw().to_string();
+    let Some(fmt) = format::replace_fn_name(&lit.value(), &name) else {
+        return Err(syn::Error::new(lit.span(), "Malformed format string"));
+    };
+    let fmt = LitStr::new(&fmt, lit.span());
+    attrs.push(parse_quote! { #[::errify::errify(#fmt)] });
+    Ok(())
+}
+
+impl ToTokens for ModuleContext {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        for item in &self.items {
+            item.to_tokens(tokens);
+        }
+    }
+}
+
+/// Checks that the function returns `Result<T, E>` or an alias named `Result`, e.g. `io::Result<T>`.
+/// Other types with two generic arguments, e.g. `HashMap<K, V>`, are skipped.
+fn returns_result(sig: &Signature) -> bool {
+    let ReturnType::Type(_, ty) = &sig.output else {
+        return false;
+    };
+    let Type::Path(path) = &**ty else {
+        return false;
+    };
+    path.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Result")
+}
+
+/// Checks that the function already has its own context.
+fn has_errify(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "errify" || segment.ident == "errify_with")
+    })
+}
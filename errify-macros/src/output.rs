@@ -1,15 +1,55 @@
-use proc_macro2::TokenStream;
-use quote::{quote, ToTokens};
-use syn::{parse_quote, spanned::Spanned, Block, Expr, ExprClosure, ImplItemFn, ReturnType};
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::{
+    ext::IdentExt, parse_quote, punctuated::Punctuated, spanned::Spanned, AttrStyle, Expr,
+    ExprClosure, Ident, ImplItemFn, LitStr, ReturnType, Signature, Token, Type,
+};
 
-use crate::input::{Args, Context, ImmediateContext, Input, LazyContext};
+use crate::{
+    format::{FormatArg, FormatString},
+    input::{named_arg, Args, Context, ImmediateContext, Input, LazyContext},
+    utils,
+};
 
 pub struct Output {
     func: ImplItemFn,
+    /// The original function exposed by the `expose_inner` or `rename` option.
+    raw_func: Option<ImplItemFn>,
 }
 
 impl Output {
     pub fn from_ast(args: Args, input: Input) -> syn::Result<Self> {
+        let output = match &input.func.sig.output {
+            ReturnType::Default => {
+                return Err(syn::Error::new(
+                    input.func.sig.output.span(),
+                    "Result<...> only supported",
+                ))
+            }
+            ReturnType::Type(_, ty) => ty,
+        };
+
+        if args.opts.rename.is_some() && args.opts.expose_inner.is_some() {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "`rename` option is not supported with `expose_inner` option",
+            ));
+        }
+        let raw_func = match (&args.opts.expose_inner, &args.opts.rename) {
+            (Some(ident), _) => {
+                let mut func = input.func.clone();
+                func.vis = parse_quote! { pub };
+                func.sig.ident = ident.clone();
+                Some(func)
+            }
+            // The wrapped function gets the new name
+            (None, Some(_)) => Some(input.func.clone()),
+            (None, None) => None,
+        };
+
+        // `impl Trait` can't annotate a local binding, it's inferred from the body instead
+        let body_ty = utils::infer_impl_trait(output);
+
         let inner_fn: ExprClosure = {
             let constness = &input.func.sig.constness;
             let unsafety = &input.func.sig.unsafety;
@@ -20,44 +60,445 @@ impl Output {
             };
             let block = input.func.block;
 
+            // The unreachable early return fixes the return type of the body, so `return`
+            // expressions are coerced to it, e.g. `Box<[u8; 2]>` into `Box<[u8]>`
             parse_quote! {
-                #constness move | | { #async_block { #unsafety { #block } } }
+                #constness move | | {
+                    #async_block {
+                        if let ::errify::__private::Some(__errify_ret) = ::errify::__private::None::<#body_ty> {
+                            return __errify_ret;
+                        }
+                        #[allow(unreachable_code, clippy::diverging_sub_expression)]
+                        let __errify_ret: #body_ty = #unsafety { #block };
+                        #[allow(unreachable_code)]
+                        __errify_ret
+                    }
+                }
             }
         };
 
         let call_expr: Expr = {
-            let output = match &input.func.sig.output {
-                ReturnType::Default => {
-                    return Err(syn::Error::new(
-                        input.func.sig.output.span(),
-                        "Result<...> only supported",
-                    ))
+            // The context argument is formatted after the call, the body borrows it
+            let ctx_arg = match &args.cx {
+                Context::Immediate(ImmediateContext::Arg { ident })
+                    if !args.opts.refs.iter().flatten().any(|r| r == ident) =>
+                {
+                    Some(ident)
                 }
-                ReturnType::Type(_, ty) => ty,
+                _ => None,
             };
+            let refs = args.opts.refs.iter().flatten().chain(ctx_arg);
             if input.func.sig.asyncness.is_some() {
                 parse_quote! {
                     {
+                        #(let #refs = &#refs;)*
                         let __errify_fn = #inner_fn;
-                        let __errify_fn_res: #output = (__errify_fn)().await;
+                        let __errify_fn_res: #body_ty = ::errify::__private::call_once(__errify_fn).await;
                         __errify_fn_res
                     }
                 }
             } else {
+                let call = if args.opts.ffi {
+                    quote! { catch_unwind }
+                } else {
+                    quote! { call_once }
+                };
                 parse_quote! {
                     {
+                        #(let #refs = &#refs;)*
                         let __errify_fn = #inner_fn;
-                        let __errify_fn_res: #output = (__errify_fn)();
+                        let __errify_fn_res: #body_ty = ::errify::__private::#call(__errify_fn);
                         __errify_fn_res
                     }
                 }
             }
         };
 
-        let cx_expr = apply_context(&call_expr, &args.cx);
+        if args.opts.eager_args
+            && !matches!(
+                args.cx,
+                Context::Immediate(ImmediateContext::Literal { .. })
+            )
+        {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "`eager_args` option requires a format string context",
+            ));
+        }
+
+        if args.opts.template
+            && !matches!(
+                args.cx,
+                Context::Immediate(ImmediateContext::Literal { .. })
+            )
+        {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "`template` option requires a format string context",
+            ));
+        }
+
+        if args.opts.no_panic && args.opts.eager_args {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "`no_panic` option is not supported with `eager_args` option",
+            ));
+        }
+
+        if args.opts.lazy {
+            if args.opts.eager_args {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "`lazy` option is not supported with `eager_args` option",
+                ));
+            }
+            if !matches!(
+                args.cx,
+                Context::Immediate(
+                    ImmediateContext::Literal { .. } | ImmediateContext::Macro { .. }
+                )
+            ) {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "`lazy` option requires a format string context",
+                ));
+            }
+        }
+
+        // Options called with the context on success too, when there is no error
+        let on_success = match (&args.opts.hook, &args.opts.telemetry) {
+            (Some(_), _) => Some("hook"),
+            (None, Some(_)) => Some("telemetry"),
+            (None, None) => None,
+        };
+
+        if let Some(name) = on_success {
+            let cx_name = match args.cx {
+                Context::Immediate(ImmediateContext::MatchSource { .. }) => Some("match_source"),
+                Context::Immediate(ImmediateContext::FromError) => Some("from_error"),
+                _ => None,
+            };
+            if let Some(cx_name) = cx_name {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!(
+                        "`{name}` option is not supported with `{cx_name}` context, \
+                         the context depends on the error"
+                    ),
+                ));
+            }
+            if args.opts.stream {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!("`{name}` option is not supported with `stream` option"),
+                ));
+            }
+        }
+
+        if matches!(
+            args.cx,
+            Context::Immediate(ImmediateContext::Variant { .. })
+        ) {
+            let conflict = if args.opts.cold {
+                Some("`cold`")
+            } else if args.opts.via.is_some() {
+                Some("`via`")
+            } else if args.opts.into.is_some() {
+                Some("`into`")
+            } else {
+                None
+            };
+            if let Some(conflict) = conflict {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!("{conflict} option is not supported with variant context"),
+                ));
+            }
+        }
+
+        if args.opts.section.is_some() {
+            let conflict = if args.opts.cold {
+                Some("`cold` option")
+            } else if args.opts.via.is_some() {
+                Some("`via` option")
+            } else if args.opts.into.is_some() {
+                Some("`into` option")
+            } else if matches!(
+                args.cx,
+                Context::Immediate(ImmediateContext::Variant { .. })
+            ) {
+                Some("variant context")
+            } else {
+                None
+            };
+            if let Some(conflict) = conflict {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!("`section` option is not supported with {conflict}"),
+                ));
+            }
+        }
+
+        if args.opts.note {
+            let conflict = if args.opts.cold {
+                Some("`cold` option")
+            } else if args.opts.via.is_some() {
+                Some("`via` option")
+            } else if args.opts.into.is_some() {
+                Some("`into` option")
+            } else if args.opts.section.is_some() {
+                Some("`section` option")
+            } else if matches!(
+                args.cx,
+                Context::Immediate(ImmediateContext::Variant { .. })
+            ) {
+                Some("variant context")
+            } else {
+                None
+            };
+            if let Some(conflict) = conflict {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!("`note` option is not supported with {conflict}"),
+                ));
+            }
+        }
+
+        if args.opts.cold && args.opts.via.is_some() {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "`cold` option is not supported with `via` option",
+            ));
+        }
+
+        if args.opts.into.is_some() {
+            let conflict = if args.opts.cold {
+                Some("`cold` option")
+            } else if args.opts.debug_only {
+                Some("`debug_only` option")
+            } else if args.opts.via.is_some() {
+                Some("`via` option")
+            } else if args.opts.skip_empty {
+                Some("`skip_empty` option")
+            } else if matches!(
+                args.cx,
+                Context::Immediate(ImmediateContext::MatchSource { .. })
+            ) {
+                Some("`match_source` context")
+            } else {
+                None
+            };
+            if let Some(conflict) = conflict {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!("`into` option is not supported with {conflict}"),
+                ));
+            }
+        }
+
+        if args.opts.with_err {
+            if !matches!(args.cx, Context::Immediate(ImmediateContext::Expr { .. })) {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "`with_err` option requires a closure context, e.g. `|err| ...`",
+                ));
+            }
+            if let Some(name) = on_success {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!(
+                        "`{name}` option is not supported with `with_err` option, \
+                         the context depends on the error"
+                    ),
+                ));
+            }
+        }
+
+        if args.opts.once_cx {
+            if !matches!(
+                args.cx,
+                Context::Immediate(ImmediateContext::Expr { .. }) | Context::Lazy(_)
+            ) {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "`once_cx` option requires a closure context, e.g. `|| ...`",
+                ));
+            }
+            if args.opts.with_err {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "`once_cx` option is not supported with `with_err` option, \
+                     the context depends on the error",
+                ));
+            }
+            // The context of the first error is reused by every later call
+            let arg_idents = utils::arg_idents(&input.func.sig);
+            let tokens = match &args.cx {
+                Context::Immediate(ImmediateContext::Expr { expr }) => expr.to_token_stream(),
+                Context::Lazy(LazyContext::Closure { def }) => def.to_token_stream(),
+                _ => TokenStream::new(),
+            };
+            if let Some(ident) = find_ident(tokens, &|ident| {
+                ident == "self" || arg_idents.contains(&ident)
+            }) {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    format!(
+                        "`once_cx` context can't refer to the argument `{ident}`, \
+                         the context of the first error is reused by every call"
+                    ),
+                ));
+            }
+        }
+
+        // A sync function returning a future, e.g. a boxed one expanded by `#[async_trait]`,
+        // the `Result` is the future output
+        let future_output = match input.func.sig.asyncness {
+            Some(_) if args.opts.stream => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "`stream` option is not supported with `async` functions",
+                ))
+            }
+            Some(_) => None,
+            None if args.opts.stream => match utils::impl_stream_item(output) {
+                Some(item) => Some(FutureOutput::Stream(item)),
+                None => return Err(syn::Error::new(
+                    output.span(),
+                    "`stream` option requires an `impl Stream<Item = Result<T, E>>` return type",
+                )),
+            },
+            None => utils::boxed_future_output(output)
+                .map(FutureOutput::Boxed)
+                .or_else(|| utils::impl_future_output(output).map(FutureOutput::Impl)),
+        };
+        let res_output = future_output.map_or(&**output, |future| future.ty());
+
+        let shape = ResultShape::from_ast(&args, res_output)?;
+
+        // `Option<T>` has no error to wrap, `match` on it would fail with a confusing type error
+        let result_ty = shape.result_ty(res_output);
+        if utils::is_option(result_ty) {
+            return Err(syn::Error::new(
+                result_ty.span(),
+                "`Option` return type is not supported, only the error of `Result<T, E>` can be wrapped",
+            ));
+        }
+
+        if args.opts.ffi {
+            let conflict = if input.func.sig.asyncness.is_some() {
+                Some("`async` functions")
+            } else if let Some(future) = future_output {
+                Some(future.kind())
+            } else if args.opts.field.is_some() {
+                Some("`field` option")
+            } else {
+                None
+            };
+            if let Some(conflict) = conflict {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!("`ffi` option is not supported with {conflict}"),
+                ));
+            }
+        }
+
+        // The function returns the `into` error type instead of the body error type
+        let ret = match &args.opts.into {
+            Some(into) => {
+                if let Some(future) = future_output {
+                    return Err(syn::Error::new(
+                        output.span(),
+                        format!("`into` option is not supported with {}", future.kind()),
+                    ));
+                }
+                let mut output = Type::clone(output);
+                let result_ty = shape.result_ty_mut(&mut output);
+                if let Some(ok_ty) = &args.opts.ok {
+                    *result_ty = parse_quote! { ::core::result::Result<#ok_ty, #into> };
+                } else if let Some(err_ty) = utils::err_ty_mut(result_ty) {
+                    *err_ty = into.clone();
+                } else if let Some(ok_ty) = utils::ok_ty(result_ty) {
+                    // Single argument alias, e.g. `anyhow::Result<T>`, the error type is replaced
+                    // along with the alias
+                    let ok_ty = ok_ty.clone();
+                    *result_ty = parse_quote! { ::core::result::Result<#ok_ty, #into> };
+                } else {
+                    return Err(syn::Error::new(
+                        result_ty.span(),
+                        "`into` option can't find the error type of the return type, \
+                         use `Result<T, E>` or `Result<T>` alias return type, \
+                         or set the ok type with the `ok` option",
+                    ));
+                }
+                let arrow = <Token![->]>::default();
+                ReturnType::Type(arrow, Box::new(output))
+            }
+            None if args.opts.ok.is_some() => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "`ok` option requires the `into` option",
+                ));
+            }
+            None => input.func.sig.output.clone(),
+        };
+
+        if let (None, Some(Type::Reference(err_ty))) =
+            (&args.opts.into, utils::err_ty(shape.result_ty(res_output)))
+        {
+            return Err(syn::Error::new(
+                err_ty.span(),
+                "Reference error types are not supported, `WrapErr` takes the error by value and \
+                 requires an owned error type",
+            ));
+        }
+        let assert_error = if args.opts.assert_error {
+            let ret_ty = match &ret {
+                ReturnType::Type(_, ty) => shape.result_ty(future_output.map_or(ty, |f| f.ty())),
+                ReturnType::Default => unreachable!("checked above"),
+            };
+            match utils::err_ty(ret_ty) {
+                Some(err_ty) => quote_spanned! {err_ty.span()=>
+                    ::errify::__private::assert_error::<#err_ty>();
+                },
+                // The error type of an alias, e.g. `anyhow::Result<T>`, is known after expansion
+                None => quote_spanned! {ret_ty.span()=>
+                    ::errify::__private::assert_error::<
+                        <#ret_ty as ::errify::__private::ResultErr>::Err,
+                    >();
+                },
+            }
+        } else {
+            quote! {}
+        };
+
+        // The return type can't be decomposed, e.g. an associated type `Self::Output`, it's checked
+        // to be a `Result` after expansion instead of failing with a type mismatch in the body
+        let result_ty = shape.result_ty(res_output);
+        let assert_result = match utils::err_ty(result_ty) {
+            None if args.opts.into.is_none() => quote_spanned! {result_ty.span()=>
+                ::errify::__private::assert_result::<#result_ty>();
+            },
+            _ => quote! {},
+        };
+
+        let cx_expr = apply_context(
+            &call_expr,
+            &args,
+            &input.func.sig,
+            &shape,
+            shape.result_ty(res_output),
+            future_output,
+        );
 
         let outer_fn: ImplItemFn = {
-            let attrs = &input.func.attrs;
+            // Inner attributes of the body (`#![...]`) are parsed into function attributes,
+            // keep them inside the generated body
+            let (attrs, inner_attrs): (Vec<_>, Vec<_>) = input
+                .func
+                .attrs
+                .iter()
+                .partition(|attr| matches!(attr.style, AttrStyle::Outer));
             let vis = &input.func.vis;
             let defaultness = &input.func.defaultness;
             let constness = &input.func.sig.constness;
@@ -65,13 +506,34 @@ impl Output {
             let unsafety = &input.func.sig.unsafety;
             let inputs = &input.func.sig.inputs;
             let abi = &input.func.sig.abi;
-            let ident = &input.func.sig.ident;
+            let ident = args.opts.rename.as_ref().unwrap_or(&input.func.sig.ident);
             let (generics_impl, _generics_ty, generics_where) =
                 input.func.sig.generics.split_for_impl();
-            let ret = &input.func.sig.output;
-            let block: Block = parse_quote! {
+            // Without `debug_assertions` of the crate using the macro, the body is called as is
+            let body = if args.opts.debug_only {
+                quote! {
+                    #[cfg(debug_assertions)]
+                    let __errify_ret = #cx_expr;
+                    #[cfg(not(debug_assertions))]
+                    let __errify_ret = #call_expr;
+                    __errify_ret
+                }
+            } else {
+                cx_expr.to_token_stream()
+            };
+            // A panic while wrapping the error (e.g. in `Display` of the context or in a hook)
+            // must not unwind out of an `extern "C"` function either, it is returned without context
+            let body = if args.opts.ffi {
+                quote! { ::errify::__private::catch_unwind(|| { #body }) }
+            } else {
+                body
+            };
+            let block = quote! {
                 {
-                    #cx_expr
+                    #(#inner_attrs)*
+                    #assert_result
+                    #assert_error
+                    #body
                 }
             };
 
@@ -81,56 +543,762 @@ impl Output {
             }
         };
 
-        Ok(Self { func: outer_fn })
+        Ok(Self {
+            func: outer_fn,
+            raw_func,
+        })
     }
 }
 
 impl ToTokens for Output {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        self.func.to_tokens(tokens)
+        self.func.to_tokens(tokens);
+        self.raw_func.to_tokens(tokens);
     }
 }
 
-pub fn apply_context(call_expr: &Expr, cx: &Context) -> Expr {
-    match cx {
-        Context::Immediate(ImmediateContext::Literal { lit, args }) => parse_quote! {
+/// Output of the future or the stream returned by a sync function.
+#[derive(Clone, Copy)]
+pub enum FutureOutput<'a> {
+    /// `Pin<Box<dyn Future<Output = T>>>`, the wrapped future is boxed too.
+    Boxed(&'a Type),
+    /// `impl Future<Output = T>`, the wrapped future is an `async` block.
+    Impl(&'a Type),
+    /// `impl Stream<Item = T>` with the `stream` option, each item is wrapped.
+    Stream(&'a Type),
+}
+
+impl<'a> FutureOutput<'a> {
+    /// Returns the output type of the future or the item type of the stream.
+    pub fn ty(&self) -> &'a Type {
+        match self {
+            Self::Boxed(ty) | Self::Impl(ty) | Self::Stream(ty) => ty,
+        }
+    }
+
+    /// Describes the return type for diagnostics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Boxed(_) | Self::Impl(_) => "a future return type",
+            Self::Stream(_) => "a stream return type",
+        }
+    }
+}
+
+/// Where the `Result` is located in the function return value.
+pub enum ResultShape {
+    /// The return value is the `Result` itself.
+    Plain,
+    /// The `Result` is the element `index` of a tuple with `len` elements.
+    Tuple { index: usize, len: usize },
+}
+
+impl ResultShape {
+    pub fn from_ast(args: &Args, output: &Type) -> syn::Result<Self> {
+        let Some(field) = &args.opts.field else {
+            return Ok(Self::Plain);
+        };
+
+        let index = field.base10_parse::<usize>()?;
+        let Type::Tuple(tuple) = output else {
+            return Err(syn::Error::new(
+                output.span(),
+                "`field` option requires a tuple return type",
+            ));
+        };
+        let len = tuple.elems.len();
+        if index >= len {
+            return Err(syn::Error::new(
+                field.span(),
+                format!("Tuple index {index} is out of range for a tuple of {len} elements"),
+            ));
+        }
+
+        Ok(Self::Tuple { index, len })
+    }
+
+    /// Returns the `Result` type from the function return type.
+    pub fn result_ty<'a>(&self, output: &'a Type) -> &'a Type {
+        match (self, output) {
+            (Self::Tuple { index, .. }, Type::Tuple(tuple)) => &tuple.elems[*index],
+            _ => output,
+        }
+    }
+
+    /// Returns the `Result` type from the function return type to replace it.
+    pub fn result_ty_mut<'a>(&self, output: &'a mut Type) -> &'a mut Type {
+        match (self, output) {
+            (Self::Tuple { index, .. }, Type::Tuple(tuple)) => &mut tuple.elems[*index],
+            (_, output) => output,
+        }
+    }
+}
+
+pub fn apply_context(
+    call_expr: &Expr,
+    args: &Args,
+    sig: &Signature,
+    shape: &ResultShape,
+    result_ty: &Type,
+    future_output: Option<FutureOutput>,
+) -> Expr {
+    // The static is shared by every call of the function, see `once_context`
+    let once_cx = quote! {
+        static __ERRIFY_ONCE_CX: ::errify::__private::OnceContext =
+            ::errify::__private::OnceContext::new();
+    };
+    // Format strings formatted before the call are guarded there, the rest on error, see below
+    let eager = |cx: TokenStream| {
+        if args.opts.no_panic {
+            quote! { ::errify::__private::no_panic_context(|| #cx) }
+        } else {
+            cx
+        }
+    };
+    // The format string refers only to owned `Copy` arguments or shared references to frozen
+    // values, which are copied into the closure before the call and can't be changed by it,
+    // so the context is formatted on error only. Returned futures and streams can't borrow them
+    let defer = !args.opts.lazy
+        && !args.opts.eager_args
+        && match &args.cx {
+            Context::Immediate(ImmediateContext::Literal { lit, args }) => {
+                deferrable_context(sig, lit, args, future_output.is_none())
+            }
+            _ => false,
+        };
+    let (cx_init, mut cx_value) = match &args.cx {
+        Context::Immediate(ImmediateContext::Literal {
+            lit,
+            args: fmt_args,
+        }) if args.opts.eager_args => (
+            eager_args_context(lit, fmt_args),
+            quote! { (__errify_cx)() },
+        ),
+        Context::Immediate(ImmediateContext::Literal {
+            lit,
+            args: fmt_args,
+        }) if args.opts.lazy => (
+            quote! { let __errify_cx = || ::errify::format_cx!(#lit, #fmt_args); },
+            quote! { (__errify_cx)() },
+        ),
+        Context::Immediate(ImmediateContext::Macro {
+            mac,
+            args: fmt_args,
+        }) if args.opts.lazy => (
+            quote! { let __errify_cx = || ::errify::format_cx!(#mac, #fmt_args); },
+            quote! { (__errify_cx)() },
+        ),
+        Context::Immediate(ImmediateContext::Literal {
+            lit,
+            args: fmt_args,
+        }) if defer => (
+            quote! { let __errify_cx = move || ::errify::format_cx!(#lit, #fmt_args); },
+            quote! { (__errify_cx)() },
+        ),
+        Context::Immediate(ImmediateContext::Literal { lit, args }) => {
+            let cx = eager(quote! { ::errify::format_cx!(#lit, #args) });
+            (quote! { let __errify_cx = #cx; }, quote! { __errify_cx })
+        }
+        Context::Immediate(ImmediateContext::Macro { mac, args }) => {
+            let cx = eager(quote! { ::errify::format_cx!(#mac, #args) });
+            (quote! { let __errify_cx = #cx; }, quote! { __errify_cx })
+        }
+        Context::Immediate(ImmediateContext::Variant { lit, args, .. }) => {
+            let cx = eager(quote! { ::errify::format_cx!(#lit, #args) });
+            (quote! { let __errify_cx = #cx; }, quote! { __errify_cx })
+        }
+        Context::Immediate(ImmediateContext::Expr { expr }) if args.opts.once_cx => (
+            quote! { #once_cx let __errify_cx = #expr; },
+            quote! { ::errify::__private::once_context(&__ERRIFY_ONCE_CX, __errify_cx) },
+        ),
+        Context::Lazy(LazyContext::Closure { def }) if args.opts.once_cx => (
+            quote! { #once_cx let __errify_cx = #def; },
+            quote! { ::errify::__private::once_context(&__ERRIFY_ONCE_CX, __errify_cx) },
+        ),
+        Context::Lazy(LazyContext::Function { path }) if args.opts.once_cx => (
+            once_cx,
+            quote! { ::errify::__private::once_context(&__ERRIFY_ONCE_CX, #path) },
+        ),
+        Context::Immediate(ImmediateContext::Expr { expr }) if args.opts.with_err => (
+            // The closure gets the error type from the result type, so its argument can be used
+            // without annotation
+            quote! { let __errify_cx = ::errify::__private::with_err::<#result_ty, _, _>(#expr); },
+            quote! { (__errify_cx)(&err) },
+        ),
+        Context::Immediate(ImmediateContext::Expr { expr }) => {
+            (quote! { let __errify_cx = #expr; }, quote! { __errify_cx })
+        }
+        Context::Immediate(ImmediateContext::Arg { ident }) => (
+            quote! {},
+            quote! { ::errify::__private::ToString::to_string(&#ident) },
+        ),
+        Context::Immediate(ImmediateContext::Cli { code, lit, args }) => {
+            let cx = eager(quote! { ::errify::format_cx!(#lit, #args) });
+            (
+                quote! { let __errify_cx = ::errify::CliContext::new(#code, #cx); },
+                quote! { __errify_cx },
+            )
+        }
+        // Called with the error before it is moved, see `err_bind` below
+        Context::Immediate(ImmediateContext::FromError) => (
+            quote! {},
+            quote! { ::errify::ErrorContext::default_context(&err) },
+        ),
+        // Selected in the error branch, see `select` below
+        Context::Immediate(ImmediateContext::MatchSource { .. }) => {
+            (quote! {}, quote! { __errify_cx })
+        }
+        Context::Lazy(LazyContext::Closure { def }) => (
+            quote! { let __errify_cx = #def; },
+            quote! { (__errify_cx)() },
+        ),
+        Context::Lazy(LazyContext::Function { path }) => (quote! {}, quote! { #path() }),
+    };
+
+    // Every error item of the stream is wrapped, the context formatted before the call is cloned
+    let cx_bound = match &args.cx {
+        Context::Immediate(ImmediateContext::Literal { .. } | ImmediateContext::Macro { .. }) => {
+            !args.opts.lazy && !args.opts.eager_args && !defer
+        }
+        Context::Immediate(ImmediateContext::Variant { .. } | ImmediateContext::Cli { .. }) => true,
+        Context::Immediate(ImmediateContext::Expr { .. }) => {
+            !args.opts.with_err && !args.opts.once_cx
+        }
+        _ => false,
+    };
+    if args.opts.stream && cx_bound {
+        cx_value = quote! { ::core::clone::Clone::clone(&#cx_value) };
+    }
+
+    // The arguments are moved into the body, so they are formatted before the call
+    let cx_init = match utils::arg_idents(sig) {
+        idents if args.opts.dump_args && !idents.is_empty() => {
+            let fmt = idents
+                .iter()
+                .map(|ident| format!("{} = {{:?}}", ident.unraw()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let fmt = LitStr::new(&fmt, Span::call_site());
+            cx_value = quote! { ::errify::__private::format!("{} ({})", #cx_value, __errify_args) };
+            quote! {
+                #cx_init
+                let __errify_args = ::errify::__private::format!(#fmt, #(#idents),*);
+            }
+        }
+        _ => cx_init,
+    };
+
+    if args.opts.no_panic {
+        // Covers lazy contexts and `Display` of expression contexts
+        cx_value = quote! { ::errify::__private::no_panic_context(|| #cx_value) };
+    }
+
+    let hook = if args.opts.hook.is_some() || args.opts.telemetry.is_some() {
+        let is_ok = match shape {
+            ResultShape::Plain => quote! { __errify_res },
+            ResultShape::Tuple { index, .. } => {
+                let index = syn::Index::from(*index);
+                quote! { __errify_res.#index }
+            }
+        };
+        let hook = args.opts.hook.iter();
+        let telemetry = args.opts.telemetry.iter();
+        let observe = quote! {
+            let __errify_cx = #cx_value;
+            let __errify_cx_str = ::errify::__private::ToString::to_string(&__errify_cx);
+            #( (#hook)(&__errify_cx_str); )*
+            #( (#telemetry)(&__errify_cx_str, ::errify::__private::Result::is_ok(&#is_ok)); )*
+        };
+        cx_value = quote! { __errify_cx };
+        observe
+    } else {
+        quote! {}
+    };
+
+    let err_bind = if args.opts.with_err
+        || matches!(args.cx, Context::Immediate(ImmediateContext::FromError))
+    {
+        // Called before the error is moved into `wrap_err`
+        let bind = quote! { let __errify_cx = #cx_value; };
+        cx_value = quote! { __errify_cx };
+        bind
+    } else {
+        quote! {}
+    };
+
+    let skip_bind = if args.opts.skip_empty {
+        let bind =
+            quote! { let __errify_cx = ::errify::__private::ToString::to_string(&#cx_value); };
+        cx_value = quote! { __errify_cx };
+        bind
+    } else {
+        quote! {}
+    };
+
+    if let Some(limit) = &args.opts.rate_limit {
+        // The counter is shared by every call of the function
+        let terse = LitStr::new(
+            &format!("{} (context rate limited)", sig.ident.unraw()),
+            sig.ident.span(),
+        );
+        cx_value = quote! {
             {
-                let __errify_cx = ::errify::format_cx!(#lit, #args);
-                let __errify_res = #call_expr;
-                match __errify_res {
-                    ::errify::__private::Ok(v) => ::errify::__private::Ok(v),
-                    ::errify::__private::Err(err) => ::errify::__private::Err(::errify::WrapErr::wrap_err(err, __errify_cx)),
+                static __ERRIFY_RATE_LIMIT: ::errify::__private::AtomicUsize =
+                    ::errify::__private::AtomicUsize::new(0);
+                if ::errify::__private::within_rate_limit(&__ERRIFY_RATE_LIMIT, #limit) {
+                    ::errify::__private::RateLimited::Full(#cx_value)
+                } else {
+                    ::errify::__private::RateLimited::Terse(#terse)
                 }
             }
-        },
-        Context::Immediate(ImmediateContext::Expr { expr }) => parse_quote! {
-            {
-                let __errify_cx = #expr;
-                let __errify_res = #call_expr;
-                match __errify_res {
-                    ::errify::__private::Ok(v) => ::errify::__private::Ok(v),
-                    ::errify::__private::Err(err) => ::errify::__private::Err(::errify::WrapErr::wrap_err(err, __errify_cx)),
+        };
+    }
+
+    if args.opts.thread {
+        cx_value = quote! { ::errify::__private::thread_context(&#cx_value) };
+    }
+
+    if let Some(key) = &args.opts.task_local {
+        cx_value = quote! { ::errify::__private::task_local_context(&#key, &#cx_value) };
+    }
+
+    if let Some(format_with) = &args.opts.format_with {
+        cx_value = quote! {
+            (#format_with)(&::errify::__private::ToString::to_string(&#cx_value))
+        };
+    }
+
+    let err_prelude = if let Some(level) = &args.opts.level {
+        let prelude = quote! {
+            let __errify_cx = #cx_value;
+            ::errify::__private::tracing::event!(
+                ::errify::__private::tracing::Level::#level,
+                error = %err,
+                "{}",
+                __errify_cx,
+            );
+        };
+        cx_value = quote! { __errify_cx };
+        prelude
+    } else {
+        quote! {}
+    };
+
+    let err_prelude = if let Some(counter) = &args.opts.counter {
+        let prelude = quote! {
+            #err_prelude
+            let __errify_cx = #cx_value;
+            ::errify::__private::metrics::counter!(
+                #counter,
+                "context" => ::errify::__private::ToString::to_string(&__errify_cx),
+            )
+            .increment(1);
+        };
+        cx_value = quote! { __errify_cx };
+        prelude
+    } else {
+        err_prelude
+    };
+
+    let err_prelude = if args.opts.defmt {
+        // `defmt` macros refer to the `defmt` crate by name, it can't be re-exported
+        let prelude = quote! {
+            #err_prelude
+            let __errify_cx = #cx_value;
+            ::defmt::error!("{}", __errify_cx);
+        };
+        cx_value = quote! { __errify_cx };
+        prelude
+    } else {
+        err_prelude
+    };
+
+    let err_prelude = if args.opts.warn_redundant {
+        let prelude = quote! {
+            #err_prelude
+            let __errify_cx = #cx_value;
+            if ::core::cfg!(debug_assertions) {
+                ::errify::__private::warn_redundant(&err, &__errify_cx);
+            }
+        };
+        cx_value = quote! { __errify_cx };
+        prelude
+    } else {
+        err_prelude
+    };
+
+    if args.opts.transform {
+        cx_value = quote! { ::errify::__private::transform_context(&#cx_value) };
+    }
+
+    // `WrapErr` is not implemented for `Box<E>`, the boxed error is wrapped by the own implementation
+    // of the box or in place, see `__private::WrapBoxed`
+    let boxed = args
+        .opts
+        .err
+        .as_ref()
+        .or(utils::err_ty(result_ty))
+        .and_then(utils::boxed_ty)
+        .is_some();
+    let (wrap_trait, wrap_target) = if boxed {
+        (
+            quote! { ::errify::__private::WrapBoxed },
+            quote! {
+                {
+                    use ::errify::__private::{BoxedKind as _, InnerKind as _};
+                    (&err).errify_kind()
+                },
+                err
+            },
+        )
+    } else {
+        (quote! { ::errify::WrapErr }, quote! { err })
+    };
+
+    let (cold_fn, wrap_err) = if args.opts.cold {
+        let cold_fn = if boxed {
+            quote! {
+                #[cold]
+                #[inline(never)]
+                fn __errify_cold<T, E, C, F>(
+                    tag: T,
+                    err: ::errify::__private::Box<E>,
+                    cx: F,
+                ) -> ::errify::__private::Box<E>
+                where
+                    T: ::errify::__private::WrapBoxed<E>,
+                    C: ::core::fmt::Display + ::core::marker::Send + ::core::marker::Sync + 'static,
+                    F: ::core::ops::FnOnce() -> C,
+                {
+                    ::errify::__private::WrapBoxed::wrap_err_with(tag, err, cx)
                 }
             }
-        },
-        Context::Lazy(LazyContext::Closure { def }) => parse_quote! {
-            {
-                let __errify_cx = #def;
-                let __errify_res = #call_expr;
-                match __errify_res {
-                    ::errify::__private::Ok(v) => ::errify::__private::Ok(v),
-                    ::errify::__private::Err(err) => ::errify::__private::Err(::errify::WrapErr::wrap_err(err, (__errify_cx)())),
+        } else {
+            quote! {
+                #[cold]
+                #[inline(never)]
+                fn __errify_cold<E, C, F>(err: E, cx: F) -> E
+                where
+                    E: ::errify::WrapErr,
+                    C: ::core::fmt::Display + ::core::marker::Send + ::core::marker::Sync + 'static,
+                    F: ::core::ops::FnOnce() -> C,
+                {
+                    ::errify::WrapErr::wrap_err_with(err, cx)
+                }
+            }
+        };
+        (
+            cold_fn,
+            quote! { __errify_cold(#wrap_target, move || #cx_value) },
+        )
+    } else {
+        let lazy = args.opts.lazy || matches!(args.cx, Context::Lazy(_));
+        let wrap_err = if let Context::Immediate(ImmediateContext::Variant {
+            path, field, ..
+        }) = &args.cx
+        {
+            // Other variants are returned as is
+            quote! {
+                {
+                    let mut err = err;
+                    if let #path { #field, .. } = &mut err {
+                        *#field = ::core::convert::Into::into(
+                            ::errify::__private::ToString::to_string(&#cx_value),
+                        );
+                    }
+                    err
+                }
+            }
+        } else {
+            match (&args.opts.via, &args.opts.into) {
+                _ if args.opts.section.is_some() => {
+                    let section = &args.opts.section;
+                    quote! { ::errify::WrapErrSection::wrap_err_section(err, #cx_value, #section) }
+                }
+                _ if args.opts.note => {
+                    quote! { ::errify::WrapErrNote::wrap_err_note(err, #cx_value) }
+                }
+                (Some(via), _) => quote! { (#via)(err, #cx_value) },
+                (None, Some(into)) => {
+                    quote! { <#into as ::errify::FromErrorContext<_>>::from_error_context(err, #cx_value) }
+                }
+                // The error type decides whether to call the lazy context
+                (None, None) if lazy => {
+                    quote! { #wrap_trait::wrap_err_with(#wrap_target, move || #cx_value) }
                 }
+                (None, None) => quote! { #wrap_trait::wrap_err(#wrap_target, #cx_value) },
+            }
+        };
+        (quote! {}, wrap_err)
+    };
+
+    let wrapped = if args.opts.skip_empty {
+        quote! {
+            if __errify_cx.is_empty() {
+                ::errify::__private::Err(err)
+            } else {
+                ::errify::__private::Err(#wrap_err)
+            }
+        }
+    } else {
+        quote! { ::errify::__private::Err(#wrap_err) }
+    };
+    // `WrapErr` and other traits are resolved for the explicit error type, e.g. a `cfg`-selected alias
+    let err_ty = args.opts.err.iter();
+    let mut err_arm = quote! {
+        #( let err: #err_ty = err; )*
+        #err_bind
+        #skip_bind
+        #err_prelude
+        #wrapped
+    };
+    if let Context::Immediate(ImmediateContext::MatchSource { arms, default }) = &args.cx {
+        // The error without a matching arm is returned as is
+        let default = match default {
+            Some(lit) => quote! { ::errify::__private::Some(::errify::format_cx!(#lit)) },
+            None => quote! { ::errify::__private::None },
+        };
+        let select = arms.iter().rev().fold(default, |acc, (ty, lit)| {
+            quote! {
+                if err.downcast_ref::<#ty>().is_some() {
+                    ::errify::__private::Some(::errify::format_cx!(#lit))
+                } else {
+                    #acc
+                }
+            }
+        });
+        err_arm = quote! {
+            match #select {
+                ::errify::__private::Some(__errify_cx) => { #err_arm }
+                ::errify::__private::None => ::errify::__private::Err(err),
+            }
+        };
+    }
+
+    let wrap = |res: &TokenStream| {
+        let wrapped = quote! {
+            match #res {
+                ::errify::__private::Ok(v) => ::errify::__private::Ok(v),
+                ::errify::__private::Err(err) => { #err_arm }
+            }
+        };
+        match &args.opts.post {
+            Some(post) => quote! { (#post)(#wrapped) },
+            None => wrapped,
+        }
+    };
+
+    let res_expr = match shape {
+        ResultShape::Plain => wrap(&quote! { __errify_res }),
+        ResultShape::Tuple { index, len } => {
+            let elems = (0..*len)
+                .map(|i| format_ident!("__errify_res_{}", i))
+                .collect::<Vec<_>>();
+            let wrapped = elems.iter().enumerate().map(|(i, elem)| {
+                if i == *index {
+                    wrap(&elem.to_token_stream())
+                } else {
+                    elem.to_token_stream()
+                }
+            });
+            quote! {
+                {
+                    let ( #(#elems,)* ) = __errify_res;
+                    ( #(#wrapped,)* )
+                }
+            }
+        }
+    };
+
+    match future_output {
+        Some(FutureOutput::Stream(item)) => parse_quote! {
+            {
+                #cold_fn
+                #cx_init
+                let __errify_stream = #call_expr;
+                ::errify::__private::WrapStream::new(
+                    __errify_stream,
+                    move |__errify_res: #item| #res_expr,
+                )
             }
         },
-        Context::Lazy(LazyContext::Function { path }) => parse_quote! {
+        Some(future_output) => {
+            let output = future_output.ty();
+            let fut = quote! {
+                async move {
+                    let __errify_res: #output = __errify_fut.await;
+                    #hook
+                    #res_expr
+                }
+            };
+            let fut = match future_output {
+                FutureOutput::Boxed(_) => quote! { ::errify::__private::Box::pin(#fut) },
+                FutureOutput::Impl(_) | FutureOutput::Stream(_) => fut,
+            };
+            parse_quote! {
+                {
+                    #cold_fn
+                    #cx_init
+                    let __errify_fut = #call_expr;
+                    #fut
+                }
+            }
+        }
+        None => parse_quote! {
             {
+                #cold_fn
+                #cx_init
                 let __errify_res = #call_expr;
-                match __errify_res {
-                    ::errify::__private::Ok(v) => ::errify::__private::Ok(v),
-                    ::errify::__private::Err(err) => ::errify::__private::Err(::errify::WrapErr::wrap_err(err, #path())),
-                }
+                #hook
+                #res_expr
             }
         },
     }
 }
+
+/// Checks that the format string context can be formatted after the call: every argument,
+/// explicit or implicitly captured, is a function argument of an owned `Copy` type or a field of it,
+/// or with `shared_refs` a shared reference to a value without interior mutability, e.g. `&str`.
+/// Any other expression, e.g. a call or a static, is evaluated before the call.
+fn deferrable_context(
+    sig: &Signature,
+    lit: &LitStr,
+    args: &Punctuated<Expr, Token![,]>,
+    shared_refs: bool,
+) -> bool {
+    fn root(expr: &Expr) -> Option<&Ident> {
+        match expr {
+            Expr::Path(path) if path.qself.is_none() => path.path.get_ident(),
+            Expr::Field(field) => root(&field.base),
+            Expr::Paren(paren) => root(&paren.expr),
+            Expr::Group(group) => root(&group.expr),
+            _ => None,
+        }
+    }
+
+    let Some(fmt) = FormatString::parse(&lit.value()) else {
+        // Malformed format string, `format!` reports it before the call
+        return false;
+    };
+    let copy_args = utils::copy_arg_idents(sig, shared_refs);
+    let is_copy_arg = |ident: &Ident| copy_args.contains(&ident);
+
+    let mut named = Vec::new();
+    for arg in args {
+        let expr = match named_arg(arg) {
+            Some((name, expr)) => {
+                named.push(name.to_string());
+                expr
+            }
+            None => arg,
+        };
+        if !root(expr).is_some_and(is_copy_arg) {
+            return false;
+        }
+    }
+    fmt.args().into_iter().all(|arg| match arg {
+        FormatArg::Name(name) => {
+            named.contains(&name) || copy_args.iter().any(|ident| **ident == name)
+        }
+        FormatArg::Next | FormatArg::Index(_) => true,
+    })
+}
+
+/// Returns the first identifier of the tokens matching the predicate, including nested groups
+/// and implicit captures of string literals used as format strings, e.g. `format!("{arg}")`.
+fn find_ident(tokens: TokenStream, pred: &dyn Fn(&Ident) -> bool) -> Option<Ident> {
+    tokens.into_iter().find_map(|tt| match tt {
+        TokenTree::Ident(ident) => pred(&ident).then_some(ident),
+        TokenTree::Group(group) => find_ident(group.stream(), pred),
+        TokenTree::Literal(lit) => {
+            let lit = syn::parse2::<LitStr>(TokenTree::Literal(lit).into()).ok()?;
+            let fmt = FormatString::parse(&lit.value())?;
+            fmt.args().into_iter().find_map(|arg| match arg {
+                FormatArg::Name(name) => syn::parse_str::<Ident>(&name)
+                    .ok()
+                    .map(|ident| Ident::new(&ident.to_string(), lit.span()))
+                    .filter(|ident| pred(ident)),
+                FormatArg::Next | FormatArg::Index(_) => None,
+            })
+        }
+        TokenTree::Punct(_) => None,
+    })
+}
+
+/// Formats each placeholder of the format string before the call,
+/// and returns a closure that concatenates them into the context.
+fn eager_args_context(lit: &LitStr, args: &Punctuated<Expr, Token![,]>) -> TokenStream {
+    let Some(mut fmt) = FormatString::parse(&lit.value()) else {
+        // Malformed format string, `format!` reports it
+        return quote! { let __errify_cx = || ::errify::__private::format!(#lit, #args); };
+    };
+
+    // Bind each argument once, so every placeholder can refer to it by name
+    let mut bindings = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        match named_arg(arg) {
+            Some((name, expr)) => bindings.push((format_ident!("__errify_arg_{}", name), expr)),
+            None => bindings.push((format_ident!("__errify_arg_{}", i), arg)),
+        }
+    }
+    let mut captures = Vec::new();
+    let mut counts = Vec::new();
+    fmt.resolve_args(|arg, is_count| {
+        let name = match arg {
+            FormatArg::Index(i) => i.to_string(),
+            FormatArg::Name(name) => {
+                let ident = format_ident!("__errify_arg_{}", name);
+                if !bindings.iter().any(|(i, _)| *i == ident) && !captures.contains(&name) {
+                    // Implicitly captured argument, `{arg}`
+                    captures.push(name.clone());
+                }
+                name
+            }
+            FormatArg::Next => unreachable!(),
+        };
+        if is_count {
+            counts.push(name.clone());
+            format!("__errify_count_{name}")
+        } else {
+            format!("__errify_arg_{name}")
+        }
+    });
+    counts.sort();
+    counts.dedup();
+
+    let bindings = bindings
+        .iter()
+        .map(|(ident, expr)| quote! { let #ident = &(#expr); });
+    let captures = captures.iter().map(|name| {
+        let ident = format_ident!("__errify_arg_{}", name);
+        let arg = Ident::new(name, lit.span());
+        quote! { let #ident = &#arg; }
+    });
+    let counts = counts.iter().map(|name| {
+        let count = format_ident!("__errify_count_{}", name);
+        let arg = format_ident!("__errify_arg_{}", name);
+        quote! { let #count: usize = *#arg; }
+    });
+    let placeholders = fmt
+        .take_placeholders()
+        .into_iter()
+        .map(|placeholder| {
+            let placeholder = LitStr::new(&placeholder, lit.span());
+            quote! { ::errify::__private::format!(#placeholder) }
+        })
+        .collect::<Vec<_>>();
+    let pieces = (0..placeholders.len())
+        .map(|i| format_ident!("__errify_piece_{}", i))
+        .collect::<Vec<_>>();
+    let lit = LitStr::new(&fmt.to_string(), lit.span());
+
+    quote! {
+        let __errify_cx = {
+            #(#bindings)*
+            #(#captures)*
+            #(#counts)*
+            #(let #pieces = #placeholders;)*
+            move || ::errify::__private::format!(#lit, #(#pieces),*)
+        };
+    }
+}
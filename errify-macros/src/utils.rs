@@ -0,0 +1,282 @@
+use syn::{
+    punctuated::Punctuated, visit::Visit, visit_mut::VisitMut, Expr, ExprAsync, ExprClosure,
+    ExprTry, FnArg, GenericArgument, Ident, Item, Pat, PathArguments, Signature, Token, Type,
+    TypeInfer, TypeParamBound,
+};
+
+/// Returns the error type of `Result<T, E>`-like type.
+pub fn err_ty(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    let mut types = args.args.iter().filter_map(|arg| match arg {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    });
+    let _ok = types.next()?;
+    types.next()
+}
+
+/// Returns the ok type of `Result<T, E>`-like type, including single argument aliases
+/// like `anyhow::Result<T>`.
+pub fn ok_ty(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    args.args.iter().find_map(|arg| match arg {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    })
+}
+
+/// Returns the error type of `Result<T, E>`-like type to replace it.
+pub fn err_ty_mut(ty: &mut Type) -> Option<&mut Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last_mut()?;
+    let PathArguments::AngleBracketed(args) = &mut segment.arguments else {
+        return None;
+    };
+
+    args.args
+        .iter_mut()
+        .filter_map(|arg| match arg {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        })
+        .nth(1)
+}
+
+/// Checks that the type is `Option<T>`, which is not `Result`-like despite the single type argument.
+pub fn is_option(ty: &Type) -> bool {
+    single_generic_ty(ty, "Option").is_some()
+}
+
+/// Returns the boxed type of `Box<E>`, except trait objects like `Box<dyn Display>`
+/// that can't be moved out of the box.
+pub fn boxed_ty(ty: &Type) -> Option<&Type> {
+    single_generic_ty(ty, "Box").filter(|ty| !matches!(ty, Type::TraitObject(_)))
+}
+
+/// Returns the output type of `Pin<Box<dyn Future<Output = T>>>`-like type.
+pub fn boxed_future_output(ty: &Type) -> Option<&Type> {
+    let pin = single_generic_ty(ty, "Pin")?;
+    let Type::TraitObject(object) = single_generic_ty(pin, "Box")? else {
+        return None;
+    };
+    future_bound_output(&object.bounds)
+}
+
+/// Returns the output type of `impl Future<Output = T>`-like type.
+pub fn impl_future_output(ty: &Type) -> Option<&Type> {
+    let Type::ImplTrait(impl_trait) = ty else {
+        return None;
+    };
+    future_bound_output(&impl_trait.bounds)
+}
+
+/// Returns the item type of `impl Stream<Item = T>`-like type.
+pub fn impl_stream_item(ty: &Type) -> Option<&Type> {
+    let Type::ImplTrait(impl_trait) = ty else {
+        return None;
+    };
+    bound_assoc_ty(&impl_trait.bounds, "Stream", "Item")
+}
+
+/// Returns `T` of the `Future<Output = T>` bound.
+fn future_bound_output(bounds: &Punctuated<TypeParamBound, Token![+]>) -> Option<&Type> {
+    bound_assoc_ty(bounds, "Future", "Output")
+}
+
+/// Returns `T` of the `Trait<Assoc = T>` bound named `trait_name`.
+fn bound_assoc_ty<'a>(
+    bounds: &'a Punctuated<TypeParamBound, Token![+]>,
+    trait_name: &str,
+    assoc_name: &str,
+) -> Option<&'a Type> {
+    bounds.iter().find_map(|bound| {
+        let TypeParamBound::Trait(bound) = bound else {
+            return None;
+        };
+        let segment = bound.path.segments.last()?;
+        if segment.ident != trait_name {
+            return None;
+        }
+        let PathArguments::AngleBracketed(args) = &segment.arguments else {
+            return None;
+        };
+        args.args.iter().find_map(|arg| match arg {
+            GenericArgument::AssocType(assoc) if assoc.ident == assoc_name => Some(&assoc.ty),
+            _ => None,
+        })
+    })
+}
+
+/// Returns the only generic type argument of the type named `name`, e.g. `Box<T>`.
+fn single_generic_ty<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != name {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    let mut types = args.args.iter().filter_map(|arg| match arg {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    });
+    match (types.next(), types.next()) {
+        (Some(ty), None) => Some(ty),
+        _ => None,
+    }
+}
+
+/// Returns the first `?` operator of the expression, that would return from the enclosing
+/// function. Closures, async blocks and nested items have their own return scope.
+pub fn find_try(expr: &Expr) -> Option<&ExprTry> {
+    struct Finder<'ast> {
+        found: Option<&'ast ExprTry>,
+    }
+
+    impl<'ast> Visit<'ast> for Finder<'ast> {
+        fn visit_expr_try(&mut self, expr: &'ast ExprTry) {
+            if self.found.is_none() {
+                self.found = Some(expr);
+            }
+        }
+
+        fn visit_expr_closure(&mut self, _: &'ast ExprClosure) {}
+
+        fn visit_expr_async(&mut self, _: &'ast ExprAsync) {}
+
+        fn visit_item(&mut self, _: &'ast Item) {}
+    }
+
+    let mut finder = Finder { found: None };
+    finder.visit_expr(expr);
+    finder.found
+}
+
+/// Replaces `impl Trait` types with `_`, so the type can annotate a local binding.
+pub fn infer_impl_trait(ty: &Type) -> Type {
+    struct Replacer;
+
+    impl VisitMut for Replacer {
+        fn visit_type_mut(&mut self, ty: &mut Type) {
+            if let Type::ImplTrait(impl_trait) = ty {
+                *ty = Type::Infer(TypeInfer {
+                    underscore_token: Token![_](impl_trait.impl_token.span),
+                });
+            } else {
+                syn::visit_mut::visit_type_mut(self, ty);
+            }
+        }
+    }
+
+    let mut ty = ty.clone();
+    Replacer.visit_type_mut(&mut ty);
+    ty
+}
+
+/// Returns the identifiers of the function arguments whose type is known to be `Copy`,
+/// except the receiver and destructuring patterns. With `shared_refs`, shared references to
+/// values without interior mutability are included, e.g. `&str`, the referenced value can't
+/// change in the function body. Other references are excluded.
+pub fn copy_arg_idents(sig: &Signature, shared_refs: bool) -> Vec<&Ident> {
+    sig.inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(arg) => match &*arg.pat {
+                Pat::Ident(pat)
+                    if pat.by_ref.is_none()
+                        && pat.subpat.is_none()
+                        && (is_copy_ty(&arg.ty) || shared_refs && is_frozen_ref_ty(&arg.ty)) =>
+                {
+                    Some(&pat.ident)
+                }
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        })
+        .collect()
+}
+
+/// Checks that the type is `Copy` and owns its value, e.g. a primitive number or a tuple of them.
+/// Other types, including references and generic ones, are assumed to be not `Copy`.
+fn is_copy_ty(ty: &Type) -> bool {
+    const PRIMITIVES: &[&str] = &[
+        "bool", "char", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64",
+        "u128", "usize", "f32", "f64",
+    ];
+
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path
+            .path
+            .get_ident()
+            .is_some_and(|ident| PRIMITIVES.iter().any(|p| ident == p)),
+        Type::Tuple(tuple) => tuple.elems.iter().all(is_copy_ty),
+        Type::Array(array) => is_copy_ty(&array.elem),
+        Type::Paren(paren) => is_copy_ty(&paren.elem),
+        Type::Group(group) => is_copy_ty(&group.elem),
+        _ => false,
+    }
+}
+
+/// Checks that the type is a shared reference to a value without interior mutability,
+/// e.g. `&str`, `&[u8]`, `&Path`, `&OsStr` or a reference to a `Copy` type of [`is_copy_ty`].
+fn is_frozen_ref_ty(ty: &Type) -> bool {
+    const UNSIZED: &[&str] = &["str", "Path", "OsStr"];
+
+    fn is_frozen_ty(ty: &Type) -> bool {
+        match ty {
+            Type::Path(path) if path.qself.is_none() => {
+                path.path.segments.last().is_some_and(|segment| {
+                    segment.arguments.is_none() && UNSIZED.iter().any(|p| segment.ident == p)
+                }) || is_copy_ty(ty)
+            }
+            Type::Slice(slice) => is_copy_ty(&slice.elem),
+            Type::Paren(paren) => is_frozen_ty(&paren.elem),
+            Type::Group(group) => is_frozen_ty(&group.elem),
+            _ => is_copy_ty(ty),
+        }
+    }
+
+    match ty {
+        Type::Reference(reference) => {
+            reference.mutability.is_none() && is_frozen_ty(&reference.elem)
+        }
+        Type::Paren(paren) => is_frozen_ref_ty(&paren.elem),
+        Type::Group(group) => is_frozen_ref_ty(&group.elem),
+        _ => false,
+    }
+}
+
+/// Returns the identifiers of the function arguments, except the receiver
+/// and destructuring patterns.
+pub fn arg_idents(sig: &Signature) -> Vec<&Ident> {
+    sig.inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(arg) => match &*arg.pat {
+                Pat::Ident(pat) => Some(&pat.ident),
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        })
+        .collect()
+}
@@ -0,0 +1,53 @@
+use alloc::borrow::Cow;
+use core::fmt::{self, Display, Formatter};
+
+/// Context with a short machine-readable code and a human-readable message for CLI tools.
+///
+/// Created by the `cli(code = "...", "...")` context of the [`errify`](crate::errify) macro.
+/// The message is displayed as the context, the code can be retrieved by downcasting
+/// the error to the context, e.g. with `anyhow::Error::downcast_ref`.
+///
+/// ```ignore
+/// use errify::{errify, CliContext};
+///
+/// #[errify(cli(code = "E042", "Could not read {path}"))]
+/// fn read(path: &str) -> Result<String, anyhow::Error> {
+///     // ...
+/// }
+///
+/// let err = read("config.toml").unwrap_err();
+/// let cx = err.downcast_ref::<CliContext>().unwrap();
+/// assert_eq!(cx.code(), "E042");
+/// assert_eq!(cx.message(), "Could not read config.toml");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliContext {
+    code: &'static str,
+    message: Cow<'static, str>,
+}
+
+impl CliContext {
+    /// Creates a context from the code and the message.
+    pub fn new(code: &'static str, message: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the short machine-readable code.
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// Returns the human-readable message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for CliContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.message, f)
+    }
+}
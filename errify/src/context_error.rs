@@ -0,0 +1,100 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Debug, Display, Formatter};
+use std::error::Error;
+
+use crate::WrapErr;
+
+/// Error wrapper that implements [`WrapErr`] for any error type.
+///
+/// Each [`WrapErr::wrap_err`] call adds a new layer of context, the latest one is displayed
+/// as the error message. The original error is available via [`ContextError::error`]
+/// or [`ContextError::into_error`].
+///
+/// ```
+/// use errify::{errify, ContextError};
+///
+/// #[errify("Custom error context, arg = {arg}")]
+/// fn func(arg: i32) -> Result<(), ContextError<std::io::Error>> {
+///     Err(std::io::Error::other("io error"))?
+/// }
+///
+/// let err = func(1).unwrap_err();
+/// assert_eq!(err.to_string(), "Custom error context, arg = 1");
+/// assert_eq!(err.error().to_string(), "io error");
+/// ```
+pub struct ContextError<E> {
+    error: E,
+    contexts: Vec<String>,
+}
+
+impl<E> ContextError<E> {
+    /// Creates an error without context.
+    pub fn new(error: E) -> Self {
+        Self {
+            error,
+            contexts: Vec::new(),
+        }
+    }
+
+    /// Returns the original error.
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Returns the original error, discarding the context.
+    pub fn into_error(self) -> E {
+        self.error
+    }
+
+    /// Returns the context layers, from the latest to the earliest one.
+    pub fn contexts(&self) -> impl Iterator<Item = &str> {
+        self.contexts.iter().rev().map(String::as_str)
+    }
+}
+
+impl<E> From<E> for ContextError<E> {
+    fn from(error: E) -> Self {
+        Self::new(error)
+    }
+}
+
+impl<E> WrapErr for ContextError<E> {
+    fn wrap_err<C>(mut self, context: C) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        self.contexts.push(alloc::format!("{context}"));
+        self
+    }
+}
+
+impl<E: Display> Display for ContextError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.contexts.last() {
+            Some(context) => f.write_str(context),
+            None => Display::fmt(&self.error, f),
+        }
+    }
+}
+
+impl<E: Debug> Debug for ContextError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContextError")
+            .field("error", &self.error)
+            .field("contexts", &self.contexts)
+            .finish()
+    }
+}
+
+impl<E> Error for ContextError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        if self.contexts.is_empty() {
+            self.error.source()
+        } else {
+            Some(&self.error)
+        }
+    }
+}
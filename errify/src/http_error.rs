@@ -0,0 +1,141 @@
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{
+    any::Any,
+    fmt::{self, Debug, Display, Formatter},
+};
+use std::error::Error;
+
+use http::StatusCode;
+
+use crate::WrapErr;
+
+/// Boxed error with an HTTP status code, e.g. returned from web handlers.
+///
+/// Implements [`WrapErr`] like [`ContextError`](crate::ContextError), each context is a new layer
+/// displayed as the error message. Wrapping with [`StatusContext`] also replaces the status code.
+/// Implement the response conversion of your web framework (e.g. `axum::response::IntoResponse`)
+/// for a newtype around it to return it from handlers.
+///
+/// ```
+/// use errify::{errify, HttpError, StatusContext, WrapErr};
+/// use http::StatusCode;
+///
+/// #[errify("Failed to find user {id}")]
+/// fn find_user(id: u32) -> Result<String, HttpError> {
+///     Err(HttpError::new(StatusCode::NOT_FOUND, "no such user"))
+/// }
+///
+/// let err = find_user(1).unwrap_err();
+/// assert_eq!(err.status(), StatusCode::NOT_FOUND);
+/// assert_eq!(err.to_string(), "Failed to find user 1");
+///
+/// let err = err.wrap_err(StatusContext::new(StatusCode::BAD_GATEWAY, "Upstream failed"));
+/// assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+/// assert_eq!(err.contexts().collect::<Vec<_>>(), ["Upstream failed", "Failed to find user 1"]);
+/// ```
+pub struct HttpError {
+    status: StatusCode,
+    error: Box<dyn Error + Send + Sync>,
+    contexts: Vec<String>,
+}
+
+impl HttpError {
+    /// Creates an error without context.
+    pub fn new(status: StatusCode, error: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self {
+            status,
+            error: error.into(),
+            contexts: Vec::new(),
+        }
+    }
+
+    /// Returns the status code of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the original error.
+    pub fn error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.error
+    }
+
+    /// Returns the context layers, from the latest to the earliest one.
+    pub fn contexts(&self) -> impl Iterator<Item = &str> {
+        self.contexts.iter().rev().map(String::as_str)
+    }
+}
+
+impl WrapErr for HttpError {
+    fn wrap_err<C>(mut self, context: C) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        if let Some(context) = (&context as &dyn Any).downcast_ref::<StatusContext>() {
+            self.status = context.status;
+        }
+        self.contexts.push(alloc::format!("{context}"));
+        self
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.contexts.last() {
+            Some(context) => f.write_str(context),
+            None => Display::fmt(&self.error, f),
+        }
+    }
+}
+
+impl Debug for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpError")
+            .field("status", &self.status)
+            .field("error", &self.error)
+            .field("contexts", &self.contexts)
+            .finish()
+    }
+}
+
+impl Error for HttpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        if self.contexts.is_empty() {
+            self.error.source()
+        } else {
+            Some(&*self.error)
+        }
+    }
+}
+
+/// Context that replaces the status code of [`HttpError`], displayed as the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusContext {
+    status: StatusCode,
+    message: String,
+}
+
+impl StatusContext {
+    /// Creates a context from the status code and the message.
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the status code.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for StatusContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
@@ -3,14 +3,32 @@
 //! This library provides the macros that provide error context for the entire function.
 //!
 //! ## Features
-//! - `anyhow`: Implements [`WrapErr`] trait for [`anyhow::Error`]
+//! - `anyhow`: Implements [`WrapErr`], [`WrapErrSrc`] and [`WrapErrNote`] traits for [`anyhow::Error`],
+//!   enables the `warn_redundant` option in macros
 //! - `eyre`: Implements [`WrapErr`] trait for [`eyre::Report`]
+//! - `color-eyre`: Implements [`WrapErrSection`] trait for [`eyre::Report`] to attach
+//!   a suggestion section with [`color-eyre`], enables the `eyre` feature
+//! - `snafu`: Implements [`WrapErr`] trait for [`snafu::Whatever`]
+//! - `http`: Provides [`HttpError`], a boxed error with an HTTP status code for web handlers,
+//!   and [`StatusContext`] to replace the status code along with the context
+//! - `tracing`: Enables logging of the error with context via [`tracing`] in macros
+//! - `metrics`: Enables incrementing a [`metrics`] counter on error in macros
+//! - `tokio`: Enables prepending a [`tokio`] task-local value (e.g. a correlation id) to the context in macros
+//! - `stream`: Enables wrapping each error item of the returned [`Stream`] in macros
+//! - `context-transform`: Enables `set_context_transform` and the `transform` option in macros
+//!   to transform the context
+//! - `defmt`: Enables logging of the context via [`defmt`] in macros, the crate using the macros
+//!   must depend on [`defmt`] itself
 //!
 //! ## Context provider
 //! There are two macros [`errify`] and [`errify_with`] that provide immediate and lazy context creation respectively.
-//! The error type **must** implement the [`WrapErr`] trait for use in macros.
+//! The error type **must** implement the [`WrapErr`] trait for use in macros,
+//! [`ContextError`] can be used to add context to any error type.
 //!
 //! Macros also support `async` functions.
+//! Only the error of the `Result` returned by the function is wrapped, errors of a returned
+//! closure (e.g. `Result<impl Fn() -> Result<T, E>, E>`) are not, apply [`ResultExt`] inside
+//! the closure to add context to them.
 //!
 //! ### Immediate context
 //!
@@ -73,6 +91,13 @@
 //! This is mean that context is created **before** call this function because of arguments, and
 //! it could lead to unnecessary allocation even for the success branch.
 //!
+//! However, if every argument of the format string is a function argument of a primitive `Copy`
+//! type (e.g. `i32`) or its field, or a shared reference to a value without interior mutability
+//! (`&str`, `&[u8]`, `&Path` or `&OsStr`), the argument is copied before the call and the context
+//! is formatted from the copy only on error, so the message is the same either way.
+//! Any other argument, e.g. a call, a `&Cell` or a static, is formatted before the call.
+//! Use the `lazy` option to format any context only on error, after the call.
+//!
 //! The context can be either the format string or any expression that fits
 //! constraint `T: Display + Send + Sync + 'static`:
 //! ```
@@ -146,11 +171,55 @@
 //! }
 //! ```
 //!
+//! ### Attribute order
+//!
+//! Attribute macros are expanded from top to bottom, and each next macro wraps the function
+//! generated by the previous one. So the macros keep the signature of the function and compose
+//! with other attribute macros, e.g. `#[tracing::instrument]`. Place `errify` above
+//! `#[instrument(err)]` to record the error with context:
+//! ```ignore
+//! #[errify("Custom error context, arg = {arg}")]
+//! #[tracing::instrument(err)]
+//! fn func(arg: i32) -> Result<(), CustomError> {
+//!     // ...
+//! }
+//! ```
+//!
+//! A sync function returning `Pin<Box<dyn Future<Output = Result<...>>>>` is wrapped as well,
+//! the context is attached to the future output. So the macros can be placed under
+//! `#[async_trait]`, which rewrites async methods into such functions:
+//! ```ignore
+//! #[async_trait]
+//! impl Trait for Struct {
+//!     #[errify("Custom error context, arg = {arg}")]
+//!     async fn func(&self, arg: i32) -> Result<(), CustomError> {
+//!         // ...
+//!     }
+//! }
+//! ```
+//!
+//! ### Call site context
+//!
+//! If you don't need context for the entire function, use the [`ResultExt`] methods
+//! `errify` and `errify_with` to wrap the error of a single [`Result`].
+//!
 //! [`WrapErr`]: crate::WrapErr
 //! [`anyhow`]: https://docs.rs/anyhow/latest/anyhow/
 //! [`eyre`]: https://docs.rs/eyre/latest/eyre/
+//! [`color-eyre`]: https://docs.rs/color-eyre/latest/color_eyre/
+//! [`tracing`]: https://docs.rs/tracing/latest/tracing/
+//! [`metrics`]: https://docs.rs/metrics/latest/metrics/
+//! [`tokio`]: https://docs.rs/tokio/latest/tokio/
+//! [`Stream`]: https://docs.rs/futures-core/latest/futures_core/stream/trait.Stream.html
+//! [`defmt`]: https://docs.rs/defmt/latest/defmt/
 //! [`anyhow::Error`]: https://docs.rs/anyhow/latest/anyhow/struct.Error.html
 //! [`eyre::Report`]: https://docs.rs/eyre/latest/eyre/struct.Report.html
+//! [`snafu::Whatever`]: https://docs.rs/snafu/latest/snafu/struct.Whatever.html
+//! [`HttpError`]: https://docs.rs/errify/latest/errify/struct.HttpError.html
+//! [`StatusContext`]: https://docs.rs/errify/latest/errify/struct.StatusContext.html
+//! [`WrapErrSection`]: https://docs.rs/errify/latest/errify/trait.WrapErrSection.html
+//! [`WrapErrNote`]: https://docs.rs/errify/latest/errify/trait.WrapErrNote.html
+//! [`WrapErrSrc`]: https://docs.rs/errify/latest/errify/trait.WrapErrSrc.html
 //! [`errify`]: errify_macros::errify
 //! [`errify_with`]: errify_macros::errify_with
 
@@ -159,14 +228,45 @@ extern crate core;
 
 #[macro_use]
 mod macros;
+mod cli_context;
+mod context_error;
+#[cfg(feature = "http")]
+mod http_error;
+#[cfg(feature = "anyhow")]
+mod note;
+mod panic_error;
+pub mod testing;
 
 use alloc::fmt::Display;
 
-pub use errify_macros::{errify, errify_with};
+pub use errify_macros::{errify, errify_with, module_context};
+
+pub use crate::{cli_context::CliContext, context_error::ContextError, panic_error::PanicError};
+
+#[cfg(feature = "http")]
+pub use crate::http_error::{HttpError, StatusContext};
+#[cfg(feature = "anyhow")]
+pub use crate::note::Notes;
 
 /// Provides the `wrap_err` method for the error type.
 ///
-/// Implement for your own error type if you want to use it as an error in macros.
+/// Implement for your own error type if you want to use it as an error in macros,
+/// or wrap it into [`ContextError`] which implements this trait for any error type.
+/// Macros also wrap boxed errors, e.g. `Result<T, Box<MyError>>`, with the implementation for
+/// `Box<MyError>` if there is one, or for `MyError` in place otherwise.
+///
+/// Type-erased errors like `Box<dyn Display>` cannot implement this trait, since there is no way
+/// to attach context to them without changing the type. Use an error type that implements
+/// [`std::error::Error`] instead, e.g. [`anyhow::Error`] or [`eyre::Report`].
+///
+/// [`anyhow::Error`]: https://docs.rs/anyhow/latest/anyhow/struct.Error.html
+/// [`eyre::Report`]: https://docs.rs/eyre/latest/eyre/struct.Report.html
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be used as an error type in `errify` macros",
+    label = "the trait `WrapErr` is not implemented for `{Self}`",
+    note = "implement `errify::WrapErr` for the error type or wrap it into `errify::ContextError`",
+    note = "type-erased errors like `Box<dyn Display>` cannot carry context, use an error type implementing `std::error::Error` instead (e.g. `anyhow::Error` or `eyre::Report`)"
+)]
 pub trait WrapErr {
     /// Wrap the error value with additional context.
     ///
@@ -174,6 +274,279 @@ pub trait WrapErr {
     fn wrap_err<C>(self, context: C) -> Self
     where
         C: Display + Send + Sync + 'static;
+
+    /// Wrap the error value with additional context that is evaluated lazily.
+    ///
+    /// Used by macros with a lazy context (e.g. [`errify_with`]) and by [`ResultExt::errify_with`].
+    /// By default the context is evaluated and passed to [`WrapErr::wrap_err`], override it
+    /// if the error type can skip the evaluation, e.g. when the context is discarded.
+    fn wrap_err_with<C, F>(self, f: F) -> Self
+    where
+        Self: Sized,
+        C: Display + Send + Sync + 'static,
+        F: FnOnce() -> C,
+    {
+        self.wrap_err(f())
+    }
+}
+
+/// Provides the `wrap_err_src` method to wrap the error with a context that is an error itself.
+///
+/// Unlike [`WrapErr::wrap_err`], the source of the context is kept in the error chain:
+/// the context is followed by the messages of its own sources, then by the wrapped error.
+/// Both the context and the wrapped error can be downcast to.
+///
+/// ```
+/// use errify::WrapErrSrc;
+///
+/// #[derive(Debug)]
+/// struct ConfigError(std::io::Error);
+///
+/// impl std::fmt::Display for ConfigError {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         f.write_str("Failed to load config")
+///     }
+/// }
+///
+/// impl std::error::Error for ConfigError {
+///     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+///         Some(&self.0)
+///     }
+/// }
+///
+/// let err = anyhow::anyhow!("parse error")
+///     .wrap_err_src(ConfigError(std::io::Error::other("io error")));
+/// assert_eq!(err.to_string(), "Failed to load config");
+/// assert_eq!(format!("{err:#}"), "Failed to load config: io error: parse error");
+/// assert!(err.downcast_ref::<ConfigError>().is_some());
+/// ```
+#[cfg(feature = "anyhow")]
+pub trait WrapErrSrc {
+    /// Wrap the error value with a context that is an error itself.
+    fn wrap_err_src<C>(self, context: C) -> Self
+    where
+        C: std::error::Error + Send + Sync + 'static;
+}
+
+/// Provides the `wrap_err_note` method to attach context as a note.
+///
+/// Used by the `note` option of macros. Unlike [`WrapErr::wrap_err`], notes attached in a row
+/// are merged into one [`Notes`] layer of the error chain, retrieved by downcasting the error.
+#[cfg(feature = "anyhow")]
+pub trait WrapErrNote {
+    /// Attach the note to the error value.
+    fn wrap_err_note<C>(self, note: C) -> Self
+    where
+        C: Display + Send + Sync + 'static;
+}
+
+/// Provides the `wrap_err_section` method to wrap the error with context and a suggestion section.
+///
+/// Used by the `section = $expr` option of macros. The section is shown in the report
+/// only if the [`color-eyre`] handler is installed.
+///
+/// [`color-eyre`]: https://docs.rs/color-eyre/latest/color_eyre/
+#[cfg(feature = "color-eyre")]
+pub trait WrapErrSection: WrapErr {
+    /// Wrap the error value with additional context and attach the suggestion section.
+    fn wrap_err_section<C, S>(self, context: C, section: S) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+        S: Display + Send + Sync + 'static;
+}
+
+/// Constructs an error from another error and the context.
+///
+/// Used by the `into = Type` option of macros to convert the error of the function body
+/// into a boundary error type, the function returns `Type` as the error type instead.
+///
+/// ```
+/// use errify::{errify, FromErrorContext};
+///
+/// #[derive(Debug)]
+/// struct BoundaryError {
+///     context: String,
+///     source: std::io::Error,
+/// }
+///
+/// impl FromErrorContext<std::io::Error> for BoundaryError {
+///     fn from_error_context<C>(error: std::io::Error, context: C) -> Self
+///     where
+///         C: std::fmt::Display + Send + Sync + 'static,
+///     {
+///         Self {
+///             context: context.to_string(),
+///             source: error,
+///         }
+///     }
+/// }
+///
+/// #[errify(into = BoundaryError, "Failed to read {path}")]
+/// fn read(path: &str) -> Result<String, std::io::Error> {
+///     std::fs::read_to_string(path)
+/// }
+///
+/// let err: BoundaryError = read("missing.txt").unwrap_err();
+/// assert_eq!(err.context, "Failed to read missing.txt");
+/// ```
+pub trait FromErrorContext<E> {
+    /// Constructs the error from the original error and the context.
+    fn from_error_context<C>(error: E, context: C) -> Self
+    where
+        C: Display + Send + Sync + 'static;
+}
+
+/// Provides the default context of the error type.
+///
+/// Used by the `from_error` context of the [`errify`] macro, so the error types
+/// used across many functions can carry their own context.
+///
+/// ```
+/// use errify::{errify, ErrorContext};
+///
+/// #[derive(Debug)]
+/// struct NotFound(String);
+///
+/// impl ErrorContext for NotFound {
+///     fn default_context(&self) -> String {
+///         format!("Failed to find {}", self.0)
+///     }
+/// }
+/// # impl errify::WrapErr for NotFound {
+/// #     fn wrap_err<C>(self, context: C) -> Self
+/// #     where
+/// #         C: std::fmt::Display + Send + Sync + 'static,
+/// #     {
+/// #         Self(context.to_string())
+/// #     }
+/// # }
+///
+/// #[errify(from_error)]
+/// fn find(name: &str) -> Result<(), NotFound> {
+///     Err(NotFound(name.to_owned()))
+/// }
+///
+/// assert_eq!(find("user").unwrap_err().0, "Failed to find user");
+/// ```
+pub trait ErrorContext {
+    /// Returns the context to wrap the error with.
+    fn default_context(&self) -> alloc::string::String;
+}
+
+/// Provides methods to wrap the error of [`Result`] with context at the call site.
+///
+/// Works the same way as [`anyhow::Context`], but for any error type that implements [`WrapErr`].
+///
+/// ```
+/// use errify::ResultExt;
+/// # struct CustomError;
+/// # impl errify::WrapErr for CustomError {
+/// #     fn wrap_err<C>(self, context: C) -> Self
+/// #     where
+/// #         C: std::fmt::Display + Send + Sync + 'static,
+/// #     {
+/// #         drop(context);
+/// #         self
+/// #     }
+/// # }
+///
+/// fn func(arg: i32) -> Result<(), CustomError> {
+///     // ...
+///     # Err(CustomError)
+/// }
+///
+/// let res = func(1).errify("Custom error context");
+/// let res = func(1).errify_with(|| format!("Custom error context, arg = {}", 1));
+/// ```
+///
+/// [`anyhow::Context`]: https://docs.rs/anyhow/latest/anyhow/trait.Context.html
+pub trait ResultExt<T, E> {
+    /// Wrap the error value with additional context.
+    fn errify<C>(self, context: C) -> Result<T, E>
+    where
+        C: Display + Send + Sync + 'static;
+
+    /// Wrap the error value with additional context that is evaluated lazily only once an error occurs.
+    fn errify_with<C, F>(self, f: F) -> Result<T, E>
+    where
+        C: Display + Send + Sync + 'static,
+        F: FnOnce() -> C;
+}
+
+impl<T, E> ResultExt<T, E> for Result<T, E>
+where
+    E: WrapErr,
+{
+    fn errify<C>(self, context: C) -> Result<T, E>
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        self.map_err(|err| err.wrap_err(context))
+    }
+
+    fn errify_with<C, F>(self, f: F) -> Result<T, E>
+    where
+        C: Display + Send + Sync + 'static,
+        F: FnOnce() -> C,
+    {
+        self.map_err(|err| err.wrap_err_with(f))
+    }
+}
+
+#[cfg(feature = "context-transform")]
+type ContextTransform = fn(&str) -> String;
+
+#[cfg(feature = "context-transform")]
+static CONTEXT_TRANSFORM: std::sync::RwLock<Option<ContextTransform>> =
+    std::sync::RwLock::new(None);
+
+/// Sets the function that transforms the context of macros with the `transform` option
+/// before wrapping, e.g. to redact or prefix it.
+///
+/// The context of such macros is formatted into a `String`, so the original context type
+/// is not preserved, e.g. [`CliContext`] can't be downcast. Other macros are not affected.
+/// The previous transform is replaced.
+///
+/// ```
+/// errify::set_context_transform(|cx| format!("[app] {cx}"));
+/// ```
+#[cfg(feature = "context-transform")]
+pub fn set_context_transform(transform: fn(&str) -> String) {
+    *CONTEXT_TRANSFORM
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(transform);
+}
+
+/// Number of errors an `anyhow::Error` can have in its chain above the root cause before
+/// the `warn_redundant` option of macros warns about wrapping it. Both context layers and
+/// intermediate source errors are counted, since `anyhow` doesn't tell them apart.
+#[cfg(feature = "anyhow")]
+pub const REDUNDANT_CONTEXT_DEPTH: usize = 4;
+
+#[cfg(feature = "anyhow")]
+type RedundantContextHandler = fn(&str, usize);
+
+#[cfg(feature = "anyhow")]
+static REDUNDANT_CONTEXT_HANDLER: std::sync::RwLock<Option<RedundantContextHandler>> =
+    std::sync::RwLock::new(None);
+
+/// Sets the function called with the context and the number of errors in the chain of the error
+/// above the root cause by the `warn_redundant` option of macros, e.g. to log with the logger
+/// of the application.
+///
+/// Without the handler, the warning is emitted as a `tracing` event if the `tracing` feature
+/// is enabled, otherwise it's printed to stderr. The previous handler is replaced.
+///
+/// ```
+/// errify::set_redundant_context_handler(|cx, depth| {
+///     eprintln!("`{cx}` wraps an error with {depth} context layers");
+/// });
+/// ```
+#[cfg(feature = "anyhow")]
+pub fn set_redundant_context_handler(handler: fn(&str, usize)) {
+    *REDUNDANT_CONTEXT_HANDLER
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(handler);
 }
 
 #[cfg(feature = "anyhow")]
@@ -186,6 +559,49 @@ impl WrapErr for anyhow::Error {
     }
 }
 
+#[cfg(feature = "anyhow")]
+impl WrapErrSrc for anyhow::Error {
+    fn wrap_err_src<C>(self, context: C) -> Self
+    where
+        C: std::error::Error + Send + Sync + 'static,
+    {
+        // The sources of the context can't be moved out of it, so their messages are kept
+        // in the chain between the context and the wrapped error
+        let sources = core::iter::successors(context.source(), |err| err.source())
+            .map(ToString::to_string)
+            .collect::<alloc::vec::Vec<_>>();
+        sources
+            .into_iter()
+            .rev()
+            .fold(self, anyhow::Error::context)
+            .context(context)
+    }
+}
+
+#[cfg(feature = "anyhow")]
+impl WrapErrNote for anyhow::Error {
+    fn wrap_err_note<C>(mut self, note: C) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        // `downcast_mut` also finds the notes below other context layers, so the notes are
+        // merged only if they start the outermost layer, otherwise a new layer is attached
+        let outermost = self
+            .chain()
+            .next()
+            .map(|err| err as *const dyn std::error::Error);
+        if let Some(notes) = self.downcast_mut::<Notes>() {
+            if outermost.is_some_and(|err| core::ptr::addr_eq(err, notes as *const Notes)) {
+                notes.notes.push(note.to_string());
+                return self;
+            }
+        }
+        self.context(Notes {
+            notes: alloc::vec![note.to_string()],
+        })
+    }
+}
+
 #[cfg(feature = "eyre")]
 impl WrapErr for eyre::Report {
     fn wrap_err<C>(self, context: C) -> Self
@@ -196,15 +612,40 @@ impl WrapErr for eyre::Report {
     }
 }
 
+#[cfg(feature = "color-eyre")]
+impl WrapErrSection for eyre::Report {
+    fn wrap_err_section<C, S>(self, context: C, section: S) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+        S: Display + Send + Sync + 'static,
+    {
+        color_eyre::Section::suggestion(eyre::Report::wrap_err(self, context), section)
+    }
+}
+
+#[cfg(feature = "snafu")]
+impl WrapErr for snafu::Whatever {
+    fn wrap_err<C>(self, context: C) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        <snafu::Whatever as snafu::FromString>::with_source(
+            alloc::boxed::Box::new(self),
+            alloc::string::ToString::to_string(&context),
+        )
+    }
+}
+
 #[doc(hidden)]
 pub mod __private {
     use alloc::fmt;
     #[doc(hidden)]
-    pub use alloc::{borrow::Cow, format};
+    pub use alloc::{borrow::Cow, boxed::Box, format, string::ToString};
     use core::fmt::Arguments;
     #[doc(hidden)]
     pub use core::{
         format_args,
+        option::Option::{None, Some},
         result::{
             Result,
             Result::{Err, Ok},
@@ -217,6 +658,341 @@ pub mod __private {
     #[cfg(feature = "eyre")]
     #[doc(hidden)]
     pub use eyre;
+    #[cfg(feature = "metrics")]
+    #[doc(hidden)]
+    pub use metrics;
+    #[cfg(feature = "tracing")]
+    #[doc(hidden)]
+    pub use tracing;
+
+    /// Result type with the error type to call the `with_err` context closure with.
+    #[diagnostic::on_unimplemented(
+        message = "`{Self}` is not a `Result` type",
+        label = "the error type can't be found",
+        note = "`errify` macros wrap the error of a `Result<T, E>` return type or its alias"
+    )]
+    #[doc(hidden)]
+    pub trait ResultErr {
+        type Err;
+    }
+
+    impl<T, E> ResultErr for Result<T, E> {
+        type Err = E;
+    }
+
+    /// Checks that the return type the macro can't decompose is a `Result`, e.g. an associated type.
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn assert_result<R: ResultErr>() {}
+
+    /// Passes the `with_err` context closure through, infers its argument type.
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn with_err<R, C, F>(f: F) -> F
+    where
+        R: ResultErr,
+        F: FnOnce(&R::Err) -> C,
+    {
+        f
+    }
+
+    /// Wraps the boxed error with the own `WrapErr` implementation of the box, e.g. for `Box<MyError>`.
+    #[doc(hidden)]
+    pub struct BoxedTag;
+
+    /// Wraps the error in the box in place.
+    #[doc(hidden)]
+    pub struct InnerTag;
+
+    /// Selects [`BoxedTag`] by autoref if `Box<E>` implements `WrapErr`, see [`InnerKind`].
+    #[doc(hidden)]
+    pub trait BoxedKind {
+        #[inline(always)]
+        fn errify_kind(&self) -> BoxedTag {
+            BoxedTag
+        }
+    }
+
+    impl<E> BoxedKind for Box<E> where Box<E>: crate::WrapErr {}
+
+    /// Selects [`InnerTag`] otherwise, `E` must implement `WrapErr` then.
+    #[doc(hidden)]
+    pub trait InnerKind {
+        #[inline(always)]
+        fn errify_kind(&self) -> InnerTag {
+            InnerTag
+        }
+    }
+
+    impl<E> InnerKind for &Box<E> {}
+
+    /// Wraps the boxed error, `WrapErr` is not implemented for `Box<E>` to keep the diagnostic
+    /// of type-erased errors like `Box<dyn Display>`.
+    #[doc(hidden)]
+    pub trait WrapBoxed<E> {
+        fn wrap_err<C>(self, err: Box<E>, context: C) -> Box<E>
+        where
+            C: fmt::Display + Send + Sync + 'static;
+
+        fn wrap_err_with<C, F>(self, err: Box<E>, f: F) -> Box<E>
+        where
+            C: fmt::Display + Send + Sync + 'static,
+            F: FnOnce() -> C;
+    }
+
+    impl<E> WrapBoxed<E> for BoxedTag
+    where
+        Box<E>: crate::WrapErr,
+    {
+        #[inline(always)]
+        fn wrap_err<C>(self, err: Box<E>, context: C) -> Box<E>
+        where
+            C: fmt::Display + Send + Sync + 'static,
+        {
+            crate::WrapErr::wrap_err(err, context)
+        }
+
+        #[inline(always)]
+        fn wrap_err_with<C, F>(self, err: Box<E>, f: F) -> Box<E>
+        where
+            C: fmt::Display + Send + Sync + 'static,
+            F: FnOnce() -> C,
+        {
+            crate::WrapErr::wrap_err_with(err, f)
+        }
+    }
+
+    impl<E: crate::WrapErr> WrapBoxed<E> for InnerTag {
+        #[inline(always)]
+        fn wrap_err<C>(self, mut err: Box<E>, context: C) -> Box<E>
+        where
+            C: fmt::Display + Send + Sync + 'static,
+        {
+            *err = (*err).wrap_err(context);
+            err
+        }
+
+        #[inline(always)]
+        fn wrap_err_with<C, F>(self, mut err: Box<E>, f: F) -> Box<E>
+        where
+            C: fmt::Display + Send + Sync + 'static,
+            F: FnOnce() -> C,
+        {
+            *err = (*err).wrap_err_with(f);
+            err
+        }
+    }
+
+    /// Formats the context and applies the transform set by [`set_context_transform`](crate::set_context_transform).
+    #[cfg(feature = "context-transform")]
+    #[doc(hidden)]
+    pub fn transform_context<C: fmt::Display>(cx: &C) -> alloc::string::String {
+        let cx = ToString::to_string(cx);
+        let transform = *crate::CONTEXT_TRANSFORM
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        match transform {
+            Some(transform) => transform(&cx),
+            None => cx,
+        }
+    }
+
+    #[doc(hidden)]
+    pub use core::sync::atomic::AtomicUsize;
+
+    /// Counts the error, returns `true` while the count is within the limit.
+    #[doc(hidden)]
+    pub fn within_rate_limit(counter: &AtomicUsize, limit: usize) -> bool {
+        use core::sync::atomic::Ordering;
+
+        counter
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_add(1))
+            .is_ok_and(|n| n < limit)
+    }
+
+    /// Full context of the first errors, or the terse one after the `rate_limit`.
+    #[doc(hidden)]
+    pub enum RateLimited<C> {
+        Full(C),
+        Terse(&'static str),
+    }
+
+    impl<C: fmt::Display> fmt::Display for RateLimited<C> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Full(cx) => fmt::Display::fmt(cx, f),
+                Self::Terse(cx) => f.write_str(cx),
+            }
+        }
+    }
+
+    /// Formats the context with the name of the current thread, or its id if it is unnamed.
+    #[doc(hidden)]
+    pub fn thread_context<C: fmt::Display>(cx: &C) -> alloc::string::String {
+        let thread = std::thread::current();
+        match thread.name() {
+            Some(name) => alloc::format!("{cx} [thread {name}]"),
+            None => alloc::format!("{cx} [thread {:?}]", thread.id()),
+        }
+    }
+
+    /// Prepends the value of the task-local to the context, if it is set for the current task.
+    #[cfg(feature = "tokio")]
+    #[doc(hidden)]
+    pub fn task_local_context<T, C>(
+        key: &'static tokio::task::LocalKey<T>,
+        cx: &C,
+    ) -> alloc::string::String
+    where
+        T: fmt::Display + 'static,
+        C: fmt::Display,
+    {
+        key.try_with(|value| alloc::format!("[{value}] {cx}"))
+            .unwrap_or_else(|_| ToString::to_string(cx))
+    }
+
+    /// Checks that the error type implements [`std::error::Error`], see the `assert_error` option.
+    #[diagnostic::on_unimplemented(
+        message = "`{Self}` doesn't implement `std::error::Error`",
+        label = "required by the `assert_error` option",
+        note = "implement `std::error::Error` for the error type or remove the `assert_error` option"
+    )]
+    #[doc(hidden)]
+    pub trait AssertError {}
+
+    impl<E: std::error::Error + ?Sized> AssertError for E {}
+
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn assert_error<E: AssertError + ?Sized>() {}
+
+    /// Stream that maps each item of the inner stream, used to wrap the error items.
+    #[cfg(feature = "stream")]
+    #[doc(hidden)]
+    pub struct WrapStream<S, F> {
+        stream: core::pin::Pin<Box<S>>,
+        f: F,
+    }
+
+    #[cfg(feature = "stream")]
+    impl<S, F> WrapStream<S, F> {
+        pub fn new(stream: S, f: F) -> Self {
+            Self {
+                stream: Box::pin(stream),
+                f,
+            }
+        }
+    }
+
+    #[cfg(feature = "stream")]
+    impl<S, F, T> futures_core::Stream for WrapStream<S, F>
+    where
+        S: futures_core::Stream,
+        F: FnMut(S::Item) -> T + Unpin,
+    {
+        type Item = T;
+
+        fn poll_next(
+            self: core::pin::Pin<&mut Self>,
+            cx: &mut core::task::Context<'_>,
+        ) -> core::task::Poll<Option<T>> {
+            let this = self.get_mut();
+            this.stream
+                .as_mut()
+                .poll_next(cx)
+                .map(|item| item.map(&mut this.f))
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.stream.size_hint()
+        }
+    }
+
+    /// Context computed once and shared by every call of the function.
+    #[doc(hidden)]
+    pub type OnceContext = std::sync::OnceLock<alloc::string::String>;
+
+    /// Returns the shared context, the provider is called only if it is not computed yet.
+    #[doc(hidden)]
+    pub fn once_context<C, F>(cell: &'static OnceContext, f: F) -> &'static str
+    where
+        C: fmt::Display,
+        F: FnOnce() -> C,
+    {
+        cell.get_or_init(|| ToString::to_string(&f()))
+    }
+
+    /// Context used if formatting the context panicked with the `no_panic` option.
+    #[doc(hidden)]
+    pub const PANICKED_CONTEXT: &str = "<context formatting panicked>";
+
+    /// Formats the context, a panic while evaluating or formatting it is caught
+    /// and the placeholder context is returned instead.
+    #[doc(hidden)]
+    pub fn no_panic_context<C, F>(f: F) -> alloc::borrow::Cow<'static, str>
+    where
+        C: fmt::Display,
+        F: FnOnce() -> C,
+    {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| ToString::to_string(&f()))) {
+            Ok(cx) => alloc::borrow::Cow::Owned(cx),
+            Err(_) => alloc::borrow::Cow::Borrowed(PANICKED_CONTEXT),
+        }
+    }
+
+    /// Calls the function body, a panic is caught and converted into the error.
+    #[doc(hidden)]
+    pub fn catch_unwind<T, E, F>(f: F) -> Result<T, E>
+    where
+        E: From<crate::PanicError>,
+        F: FnOnce() -> Result<T, E>,
+    {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+            Ok(res) => res,
+            Err(payload) => Err(From::from(crate::PanicError::from_payload(payload))),
+        }
+    }
+
+    /// Warns if the error already has more than [`REDUNDANT_CONTEXT_DEPTH`](crate::REDUNDANT_CONTEXT_DEPTH)
+    /// errors in its chain above the root cause, which may indicate redundant annotations.
+    #[cfg(feature = "anyhow")]
+    #[doc(hidden)]
+    pub fn warn_redundant<C: fmt::Display>(err: &anyhow::Error, cx: &C) {
+        // Context layers and source errors can't be told apart, only the root cause is skipped
+        let depth = err.chain().count() - 1;
+        if depth <= crate::REDUNDANT_CONTEXT_DEPTH {
+            return;
+        }
+        let cx = ToString::to_string(cx);
+        let handler = *crate::REDUNDANT_CONTEXT_HANDLER
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        match handler {
+            Some(handler) => handler(&cx, depth),
+            None => report_redundant(&cx, depth),
+        }
+    }
+
+    #[cfg(all(feature = "anyhow", feature = "tracing"))]
+    fn report_redundant(cx: &str, depth: usize) {
+        tracing::warn!(
+            context = cx,
+            depth,
+            "context wraps an error with a long chain"
+        );
+    }
+
+    #[cfg(all(feature = "anyhow", not(feature = "tracing")))]
+    fn report_redundant(cx: &str, depth: usize) {
+        std::eprintln!("warning: `{cx}` wraps an error with {depth} errors in its chain");
+    }
+
+    /// Calls the function body closure, that can mutate its captured arguments.
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn call_once<R>(f: impl FnOnce() -> R) -> R {
+        f()
+    }
 
     #[doc(hidden)]
     #[inline]
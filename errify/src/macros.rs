@@ -3,13 +3,58 @@
 #[macro_export]
 macro_rules! format_cx {
     ($msg:literal $(,)?) => {
-        $crate::__private::format_err(format_args!($msg))
+        $crate::__private::format_err($crate::__private::format_args!($msg))
     };
     ($fmt:expr, $($arg:tt)*) => {
         $crate::__private::Cow::<'static, str>::Owned($crate::__private::format!($fmt, $($arg)*))
     };
 }
 
+/// Implements [`WrapErr`](crate::WrapErr) for an error enum with a dedicated context variant.
+///
+/// The variant must have the shape `WithContext { source: Box<Self>, context: String }`,
+/// wrapping boxes the error into it along with the context.
+///
+/// ```
+/// #[derive(Debug)]
+/// enum MyError {
+///     NotFound,
+///     WithContext {
+///         source: Box<MyError>,
+///         context: String,
+///     },
+/// }
+///
+/// errify::wrap_err_via_variant!(MyError::WithContext);
+///
+/// #[errify::errify("Failed to find {id}")]
+/// fn find(id: u32) -> Result<(), MyError> {
+///     Err(MyError::NotFound)
+/// }
+///
+/// let MyError::WithContext { source, context } = find(1).unwrap_err() else {
+///     unreachable!()
+/// };
+/// assert!(matches!(*source, MyError::NotFound));
+/// assert_eq!(context, "Failed to find 1");
+/// ```
+#[macro_export]
+macro_rules! wrap_err_via_variant {
+    ($ty:ident :: $variant:ident $(,)?) => {
+        impl $crate::WrapErr for $ty {
+            fn wrap_err<C>(self, context: C) -> Self
+            where
+                C: ::core::fmt::Display + ::core::marker::Send + ::core::marker::Sync + 'static,
+            {
+                $ty::$variant {
+                    source: $crate::__private::Box::new(self),
+                    context: $crate::__private::ToString::to_string(&context),
+                }
+            }
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use alloc::borrow::Cow;
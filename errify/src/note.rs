@@ -0,0 +1,44 @@
+use alloc::string::String;
+use core::fmt::{self, Debug, Display, Formatter};
+
+/// Notes attached to an [`anyhow::Error`] by [`WrapErrNote::wrap_err_note`].
+///
+/// Attached as a single context layer displayed as the notes separated by `; `, notes
+/// attached in a row are merged into the layer. The wrapped error stays downcastable and
+/// keeps its backtrace. Downcast the error to get the notes:
+///
+/// ```
+/// use errify::{Notes, WrapErrNote};
+///
+/// let err = anyhow::Error::new(std::fmt::Error)
+///     .wrap_err_note("note 1")
+///     .wrap_err_note("note 2");
+/// assert_eq!(format!("{err:#}"), "note 1; note 2: an error occurred when formatting an argument");
+/// assert_eq!(err.downcast_ref::<Notes>().unwrap().notes(), ["note 1", "note 2"]);
+/// assert!(err.is::<std::fmt::Error>());
+/// ```
+///
+/// [`anyhow::Error`]: https://docs.rs/anyhow/latest/anyhow/struct.Error.html
+/// [`WrapErrNote::wrap_err_note`]: crate::WrapErrNote::wrap_err_note
+pub struct Notes {
+    pub(crate) notes: Vec<String>,
+}
+
+impl Notes {
+    /// Returns the notes in the order they were attached, innermost first.
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+}
+
+impl Display for Notes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.notes.join("; "))
+    }
+}
+
+impl Debug for Notes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Notes").field("notes", &self.notes).finish()
+    }
+}
@@ -0,0 +1,55 @@
+use alloc::{borrow::Cow, boxed::Box};
+use core::{
+    any::Any,
+    fmt::{self, Display, Formatter},
+};
+use std::error::Error;
+
+/// Error created from a panic caught by the `ffi` option of macros.
+///
+/// The error type of the function must implement `From<PanicError>`, the context is attached
+/// to the converted error as to any other error of the function.
+///
+/// ```
+/// use errify::{errify, ContextError, PanicError};
+///
+/// #[errify(ffi, "Failed to compute {arg}")]
+/// fn func(arg: i32) -> Result<i32, ContextError<PanicError>> {
+///     panic!("boom");
+/// }
+///
+/// let err = func(1).unwrap_err();
+/// assert_eq!(err.to_string(), "Failed to compute 1");
+/// assert_eq!(err.error().message(), "boom");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicError {
+    message: Cow<'static, str>,
+}
+
+impl PanicError {
+    /// Creates an error from the payload of a panic.
+    pub fn from_payload(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<&'static str>() {
+            Ok(message) => Cow::Borrowed(*message),
+            Err(payload) => match payload.downcast::<alloc::string::String>() {
+                Ok(message) => Cow::Owned(*message),
+                Err(_) => Cow::Borrowed("Box<dyn Any>"),
+            },
+        };
+        Self { message }
+    }
+
+    /// Returns the panic message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for PanicError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked: {}", self.message)
+    }
+}
+
+impl Error for PanicError {}
@@ -0,0 +1,70 @@
+//! Helpers for testing functions that return errors with context.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::ContextError;
+
+/// Returns the original error without context, so errors can be compared regardless of context.
+///
+/// ```
+/// use errify::{errify, testing::strip_context, ContextError};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct CustomError(i32);
+///
+/// #[errify("Custom error context, arg = {arg}")]
+/// fn func(arg: i32) -> Result<(), ContextError<CustomError>> {
+///     Err(CustomError(arg))?
+/// }
+///
+/// assert_eq!(strip_context(func(1).unwrap_err()), CustomError(1));
+/// ```
+pub fn strip_context<E>(err: ContextError<E>) -> E {
+    err.into_error()
+}
+
+/// Counts the calls of a context provider, e.g. to check that a lazy context is not evaluated
+/// when the function succeeds.
+///
+/// ```
+/// use errify::{errify_with, testing::CallCounter, ContextError};
+///
+/// static CALLS: CallCounter = CallCounter::new();
+///
+/// #[errify_with(|| CALLS.count(format!("Failed with arg = {arg}")))]
+/// fn func(arg: i32) -> Result<i32, ContextError<std::io::Error>> {
+///     if arg < 0 {
+///         Err(std::io::Error::other("negative"))?
+///     }
+///     Ok(arg)
+/// }
+///
+/// func(1).unwrap();
+/// assert_eq!(CALLS.calls(), 0);
+/// func(-1).unwrap_err();
+/// assert_eq!(CALLS.calls(), 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct CallCounter {
+    calls: AtomicUsize,
+}
+
+impl CallCounter {
+    /// Creates a counter with no calls, usable in `static` items.
+    pub const fn new() -> Self {
+        Self {
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Counts the call and returns the value as is.
+    pub fn count<T>(&self, value: T) -> T {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        value
+    }
+
+    /// Returns the number of counted calls.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "color-eyre")]
+
+use errify::errify;
+
+#[test]
+fn section() {
+    // Only the report hook without colors, the panic hook is kept
+    let (_, eyre_hook) = color_eyre::config::HookBuilder::blank()
+        .theme(color_eyre::config::Theme::new())
+        .into_hooks();
+    eyre_hook.install().unwrap();
+
+    #[errify(section = "try a positive argument", "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, eyre::Report> {
+        Err(eyre::eyre!("error {arg}"))
+    }
+
+    let err = func(-1).unwrap_err();
+    assert_eq!(err.to_string(), "literal -1");
+    assert_eq!(err.root_cause().to_string(), "error -1");
+
+    let report = format!("{err:?}");
+    assert!(
+        report.contains("Suggestion: try a positive argument"),
+        "{report}"
+    );
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "context-transform")]
+
+mod utils;
+
+use std::ops::Deref;
+
+use errify::{errify, errify_with};
+use utils::*;
+
+#[test]
+fn transform() {
+    #[errify(transform, "literal {arg}")]
+    fn literal(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify_with(transform, cold, || format!("closure {arg}"))]
+    fn closure(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(transform, ContextExpr::new(2))]
+    fn expr(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify("untransformed {arg}")]
+    fn untransformed(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = literal(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+
+    errify::set_context_transform(|cx| cx.to_uppercase());
+
+    let err = literal(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("LITERAL 1"));
+
+    let err = closure(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("CLOSURE 1"));
+
+    let err = expr(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("CONTEXTEXPR(2)"));
+
+    // Macros without the option are not affected
+    let err = untransformed(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("untransformed 1"));
+}
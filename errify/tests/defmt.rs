@@ -0,0 +1,54 @@
+#![cfg(feature = "defmt")]
+
+use std::{
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use errify::{errify, errify_with, ContextError};
+
+static LOGS: AtomicUsize = AtomicUsize::new(0);
+
+#[defmt::global_logger]
+struct Logger;
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {
+        LOGS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("{=u32}", 0);
+
+#[test]
+fn log_on_error() {
+    #[errify(defmt, "literal {arg}")]
+    fn literal(arg: i32, fail: bool) -> Result<i32, ContextError<io::Error>> {
+        if fail {
+            Err(io::Error::other(format!("error {arg}")))?
+        }
+        Ok(arg)
+    }
+
+    #[errify_with(defmt, || "closure")]
+    fn closure(arg: i32) -> Result<i32, ContextError<io::Error>> {
+        Err(io::Error::other(format!("error {arg}")))?
+    }
+
+    assert_eq!(literal(1, false).unwrap(), 1);
+    assert_eq!(LOGS.load(Ordering::SeqCst), 0);
+
+    let err = literal(1, true).unwrap_err();
+    assert_eq!(err.contexts().collect::<Vec<_>>(), ["literal 1"]);
+    assert_eq!(LOGS.load(Ordering::SeqCst), 1);
+
+    let err = closure(1).unwrap_err();
+    assert_eq!(err.contexts().collect::<Vec<_>>(), ["closure"]);
+    assert_eq!(LOGS.load(Ordering::SeqCst), 2);
+}
@@ -1,6 +1,9 @@
 mod utils;
 
-use std::ops::Deref;
+use std::{
+    fmt::{Display, Formatter},
+    ops::Deref,
+};
 
 use errify::errify;
 use utils::*;
@@ -65,6 +68,54 @@ async fn async_expr() {
     assert_eq!(err.cx.as_deref(), Some("ContextExpr(2)"));
 }
 
+#[tokio::test]
+async fn async_generic_output() {
+    trait Op {
+        type Ok;
+
+        async fn run(&self, fail: bool) -> Result<Self::Ok, ErrorWithContext>
+        where
+            Self::Ok: Send;
+    }
+
+    #[derive(Debug)]
+    struct Struct;
+
+    impl Op for Struct {
+        type Ok = i32;
+
+        #[errify("literal self = {self:?}")]
+        async fn run(&self, fail: bool) -> Result<Self::Ok, ErrorWithContext>
+        where
+            Self::Ok: Send,
+        {
+            if fail {
+                return Err(ErrorWithContext::new(0));
+            }
+            Ok(Default::default())
+        }
+    }
+
+    #[errify("literal {v:?}")]
+    async fn func<T>(v: T, fail: bool) -> Result<T, ErrorWithContext>
+    where
+        T: std::fmt::Debug + Send,
+    {
+        if fail {
+            return Err(ErrorWithContext::new(0));
+        }
+        Ok(v)
+    }
+
+    assert_eq!(Struct.run(false).await.unwrap(), 0);
+    let err = Struct.run(true).await.unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal self = Struct"));
+
+    assert_eq!(func("v", false).await.unwrap(), "v");
+    let err = func("v", true).await.unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal \"v\""));
+}
+
 #[test]
 fn unsafe_literal() {
     #[errify("literal {arg}")]
@@ -133,6 +184,46 @@ fn method() {
     );
 }
 
+#[test]
+fn self_field_and_param() {
+    struct Struct {
+        id: i32,
+        inner: (&'static str, f64),
+    }
+
+    impl Struct {
+        #[errify("{self.id} processing {arg}, {self.inner.0} {:.1}", self.inner.1)]
+        fn func(&self, arg: String) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+
+        #[errify(lazy, "{self.id} processing {arg:?}")]
+        fn lazy(&self, arg: &str) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+
+        #[errify(eager_args, "{self.id:>3} processing {arg}")]
+        fn eager(&self, arg: i32) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+    }
+
+    let s = Struct {
+        id: 7,
+        inner: ("inner", 1.25),
+    };
+
+    let err = s.func("argument".to_owned()).unwrap_err();
+    assert_eq!(err.msg.deref(), "argument");
+    assert_eq!(err.cx.as_deref(), Some("7 processing argument, inner 1.2"));
+
+    let err = s.lazy("argument").unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("7 processing \"argument\""));
+
+    let err = s.eager(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("  7 processing 1"));
+}
+
 #[test]
 fn trait_method() {
     trait Trait {
@@ -157,6 +248,132 @@ fn trait_method() {
     );
 }
 
+#[test]
+fn builder_method() {
+    #[derive(Debug, Default)]
+    struct Builder {
+        size: usize,
+        name: String,
+    }
+
+    impl Builder {
+        #[errify("literal self = {self:?}, size = {size}")]
+        fn with_size(mut self, size: usize) -> Result<Self, ErrorWithContext> {
+            if size == 0 {
+                return Err(ErrorWithContext::new(size));
+            }
+            self.size = size;
+            Ok(self)
+        }
+
+        #[errify("literal name = {name}")]
+        fn with_name(self, name: &str) -> Result<Self, ErrorWithContext> {
+            if name.is_empty() {
+                return Err(ErrorWithContext::new("empty"));
+            }
+            Ok(Self {
+                name: name.to_owned(),
+                ..self
+            })
+        }
+    }
+
+    let builder = Builder::default()
+        .with_size(1)
+        .and_then(|b| b.with_name("name"))
+        .unwrap();
+    assert_eq!(builder.size, 1);
+    assert_eq!(builder.name, "name");
+
+    let err = Builder::default().with_size(0).unwrap_err();
+    assert_eq!(err.msg.deref(), "0");
+    assert_eq!(
+        err.cx.as_deref(),
+        Some("literal self = Builder { size: 0, name: \"\" }, size = 0")
+    );
+
+    let err = Builder::default().with_name("").unwrap_err();
+    assert_eq!(err.msg.deref(), "empty");
+    assert_eq!(err.cx.as_deref(), Some("literal name = "));
+}
+
+#[test]
+fn mut_self_method() {
+    #[derive(Debug, Default)]
+    struct Counter {
+        count: usize,
+    }
+
+    impl Counter {
+        // The context is formatted before the body mutates `self`
+        #[errify("increment {} by {step}", self.count)]
+        fn increment(&mut self, step: usize) -> Result<usize, ErrorWithContext> {
+            self.count += step;
+            if self.count > 2 {
+                return Err(ErrorWithContext::new(self.count));
+            }
+            Ok(self.count)
+        }
+    }
+
+    let mut counter = Counter::default();
+    assert_eq!(counter.increment(2).unwrap(), 2);
+
+    let err = counter.increment(3).unwrap_err();
+    assert_eq!(err.msg.deref(), "5");
+    assert_eq!(err.cx.as_deref(), Some("increment 2 by 3"));
+    assert_eq!(counter.count, 5);
+}
+
+#[test]
+fn generic_method_where_clause() {
+    struct Store<K> {
+        keys: Vec<K>,
+    }
+
+    impl<K> Store<K>
+    where
+        K: PartialEq + Display,
+    {
+        #[errify("find {key} in {} keys", self.keys.len())]
+        fn find<Q>(&self, key: &Q) -> Result<usize, ErrorWithContext>
+        where
+            Q: Display + ?Sized,
+            K: PartialEq<Q>,
+        {
+            self.keys
+                .iter()
+                .position(|k| k == key)
+                .ok_or_else(|| ErrorWithContext::new(key))
+        }
+
+        #[errify("into {key}")]
+        fn into_key<Q>(self, key: Q) -> Result<K, ErrorWithContext>
+        where
+            Q: Into<K> + Display,
+        {
+            let key = key.into();
+            self.keys
+                .into_iter()
+                .find(|k| *k == key)
+                .ok_or_else(|| ErrorWithContext::new(key))
+        }
+    }
+
+    let store = Store {
+        keys: vec!["a".to_owned(), "b".to_owned()],
+    };
+    assert_eq!(store.find("b").unwrap(), 1);
+
+    let err = store.find("c").unwrap_err();
+    assert_eq!(err.msg.deref(), "c");
+    assert_eq!(err.cx.as_deref(), Some("find c in 2 keys"));
+
+    let err = store.into_key("d").unwrap_err();
+    assert_eq!(err.msg.deref(), "d");
+    assert_eq!(err.cx.as_deref(), Some("into d"));
+}
+
 #[test]
 fn check_visibility() {
     pub mod multiple {
@@ -190,6 +407,130 @@ fn anyhow_error() {
     assert_eq!(custom_err, "error 1");
 }
 
+#[cfg(feature = "anyhow")]
+#[test]
+fn anyhow_downcast_root() {
+    #[errify("eager {arg}")]
+    fn eager(arg: i32) -> Result<i32, anyhow::Error> {
+        Err(StringError(format!("root {arg}")).into())
+    }
+
+    #[errify(lazy, cold, "lazy {arg}")]
+    fn lazy(arg: i32) -> Result<i32, anyhow::Error> {
+        Err(StringError(format!("root {arg}")).into())
+    }
+
+    for err in [eager(1).unwrap_err(), lazy(1).unwrap_err()] {
+        let root = err.downcast_ref::<StringError>().unwrap();
+        assert_eq!(root.deref(), "root 1");
+    }
+
+    let err = eager(2).unwrap_err();
+    assert_eq!(err.to_string(), "eager 2");
+    assert_eq!(err.downcast::<StringError>().unwrap().deref(), "root 2");
+}
+
+#[cfg(feature = "anyhow")]
+#[test]
+fn note() {
+    use errify::Notes;
+
+    #[errify(note, "inner {arg}")]
+    fn inner(arg: i32) -> Result<i32, anyhow::Error> {
+        Err(anyhow::Error::new(StringError("root".to_owned())).context("chained"))
+    }
+
+    #[errify(note, "outer {arg}")]
+    fn outer(arg: i32) -> Result<i32, anyhow::Error> {
+        inner(arg + 1)
+    }
+
+    let err = outer(1).unwrap_err();
+    let notes = err.downcast_ref::<Notes>().unwrap();
+    assert_eq!(notes.notes(), ["inner 2", "outer 1"]);
+
+    // Notes are merged into one layer of the chain
+    assert_eq!(err.to_string(), "inner 2; outer 1");
+    assert_eq!(format!("{err:#}"), "inner 2; outer 1: chained: root");
+    let chain = err.chain().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(chain, ["inner 2; outer 1", "chained", "root"]);
+
+    // The original error is still reachable
+    assert_eq!(err.root_cause().to_string(), "root");
+    assert!(err.is::<StringError>());
+    assert_eq!(err.downcast_ref::<StringError>().unwrap().deref(), "root");
+}
+
+#[cfg(feature = "anyhow")]
+#[test]
+fn note_over_context() {
+    use errify::{Notes, WrapErrNote};
+
+    let err = anyhow::Error::new(StringError("root".to_owned()))
+        .wrap_err_note("n1")
+        .context("outer ctx")
+        .wrap_err_note("n2")
+        .wrap_err_note("n3");
+    assert_eq!(format!("{err:#}"), "n2; n3: outer ctx: n1: root");
+    assert_eq!(err.downcast_ref::<Notes>().unwrap().notes(), ["n2", "n3"]);
+    assert!(err.is::<StringError>());
+}
+
+#[cfg(all(feature = "anyhow", feature = "tracing", debug_assertions))]
+#[test]
+fn warn_redundant() {
+    use tracing::Level;
+    use utils::tracing_capture::TracingCapture;
+
+    #[errify(warn_redundant, "outer {depth}")]
+    fn func(depth: usize) -> Result<i32, anyhow::Error> {
+        let mut err = anyhow::anyhow!("root");
+        for i in 0..depth {
+            err = err.context(format!("layer {i}"));
+        }
+        Err(err)
+    }
+
+    // Without the handler, the warning is emitted as a `tracing` event
+    let capture = TracingCapture::default();
+    let err = tracing::subscriber::with_default(capture.clone(), || {
+        func(errify::REDUNDANT_CONTEXT_DEPTH).unwrap_err()
+    });
+    assert_eq!(err.to_string(), "outer 4");
+    assert!(capture.events().is_empty());
+
+    let err = tracing::subscriber::with_default(capture.clone(), || func(6).unwrap_err());
+    assert_eq!(err.to_string(), "outer 6");
+    assert_eq!(err.chain().count(), 8);
+    assert_eq!(
+        capture.events(),
+        [(
+            Level::WARN,
+            "message = context wraps an error with a long chain, context = \"outer 6\", depth = 6"
+                .to_owned()
+        )]
+    );
+}
+
+#[cfg(feature = "anyhow")]
+#[test]
+fn cli_context() {
+    use errify::CliContext;
+
+    #[errify(cli(code = "E042", "could not read {path}"))]
+    fn read(path: &str) -> Result<i32, anyhow::Error> {
+        Err(anyhow::anyhow!("error {path}"))
+    }
+
+    let err = read("config.toml").unwrap_err();
+    assert_eq!(err.to_string(), "could not read config.toml");
+    assert_eq!(err.root_cause().to_string(), "error config.toml");
+
+    let cx = err.downcast_ref::<CliContext>().unwrap();
+    assert_eq!(cx.code(), "E042");
+    assert_eq!(cx.message(), "could not read config.toml");
+}
+
 #[cfg(feature = "eyre")]
 #[test]
 fn eyre_error() {
@@ -204,3 +545,2154 @@ fn eyre_error() {
     assert_eq!(context_err, "literal 1 = 1");
     assert_eq!(custom_err, "error 1");
 }
+
+#[cfg(feature = "snafu")]
+#[test]
+fn snafu_error() {
+    use std::error::Error;
+
+    #[errify("literal {arg} = {}", arg)]
+    fn func(arg: i32) -> Result<i32, snafu::Whatever> {
+        snafu::whatever!("error {}", arg)
+    }
+
+    let err = func(1).unwrap_err();
+    let context_err = err.to_string();
+    let custom_err = err.source().unwrap().to_string();
+    assert_eq!(context_err, "literal 1 = 1");
+    assert_eq!(custom_err, "error 1");
+}
+
+#[test]
+fn ref_capture() {
+    #[errify(ref(big), "literal {} {}", big.len(), arg)]
+    fn func(big: Vec<u8>, arg: String) -> Result<i32, ErrorWithContext> {
+        let _: &Vec<u8> = big;
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(vec![0; 3], "argument".to_owned()).unwrap_err();
+    assert_eq!(err.msg.deref(), "argument");
+    assert_eq!(err.cx.as_deref(), Some("literal 3 argument"));
+}
+
+#[test]
+fn tuple_field() {
+    #[errify(field = 1, "literal {arg}")]
+    fn func(arg: i32) -> (&'static str, Result<i32, ErrorWithContext>) {
+        ("meta", Err(ErrorWithContext::new(arg)))
+    }
+
+    let (meta, res) = func(1);
+    let err = res.unwrap_err();
+    assert_eq!(meta, "meta");
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[test]
+fn cold() {
+    #[errify(cold, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[test]
+fn thread() {
+    #[errify(thread, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = std::thread::Builder::new()
+        .name("worker-1".to_owned())
+        .spawn(|| func(1).unwrap_err())
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1 [thread worker-1]"));
+}
+
+#[test]
+fn ffi() {
+    #[errify(ffi, "literal {arg}")]
+    #[allow(improper_ctypes_definitions)]
+    extern "C" fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        match arg {
+            0 => Ok(arg),
+            1 => Err(ErrorWithContext::new(arg)),
+            _ => panic!("panic {arg}"),
+        }
+    }
+
+    assert_eq!(func(0).unwrap(), 0);
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+
+    // The panic doesn't unwind out of the `extern "C"` function
+    let err = func(2).unwrap_err();
+    assert_eq!(err.msg.deref(), "panicked: panic 2");
+    assert_eq!(err.cx.as_deref(), Some("literal 2"));
+}
+
+#[test]
+fn ffi_context_panic() {
+    struct PanickingContext;
+
+    impl std::fmt::Display for PanickingContext {
+        fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            panic!("context panic")
+        }
+    }
+
+    #[errify(ffi, PanickingContext)]
+    #[allow(improper_ctypes_definitions)]
+    extern "C" fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    // The panic while wrapping doesn't unwind out of the `extern "C"` function either
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "panicked: context panic");
+    assert_eq!(err.cx, None);
+}
+
+#[test]
+fn once_cx() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COMPUTES: AtomicUsize = AtomicUsize::new(0);
+
+    fn expensive() -> String {
+        COMPUTES.fetch_add(1, Ordering::SeqCst);
+        "expensive context".to_owned()
+    }
+
+    #[errify(once_cx, || expensive())]
+    fn func(attempt: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(attempt))
+    }
+
+    // The context is computed on the first error and reused by the retries
+    for attempt in 0..3 {
+        let err = func(attempt).unwrap_err();
+        assert_eq!(*err.msg, attempt.to_string());
+        assert_eq!(err.cx.as_deref(), Some("expensive context"));
+    }
+    assert_eq!(COMPUTES.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn dump_args() {
+    #[derive(Debug)]
+    struct Struct;
+
+    impl Struct {
+        #[errify(args, "literal")]
+        fn func(&self, arg: i32, r#name: &str) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+    }
+
+    #[errify(args, "literal")]
+    fn no_args() -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(0))
+    }
+
+    let err = Struct.func(1, "x").unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal (arg = 1, name = \"x\")"));
+
+    // Without arguments the context is kept as is
+    let err = no_args().unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal"));
+}
+
+#[test]
+fn debug_only() {
+    #[errify(debug_only, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        if arg == 0 {
+            return Ok(arg);
+        }
+        Err(ErrorWithContext::new(arg))
+    }
+
+    assert_eq!(func(0).unwrap(), 0);
+
+    // Run with `--release` to check the build without context
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    if cfg!(debug_assertions) {
+        assert_eq!(err.cx.as_deref(), Some("literal 1"));
+    } else {
+        assert_eq!(err.cx, None);
+    }
+}
+
+#[test]
+fn rate_limit() {
+    #[errify(rate_limit = 2, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let contexts = (0..4)
+        .map(|arg| func(arg).unwrap_err().cx.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        contexts,
+        [
+            "literal 0",
+            "literal 1",
+            "func (context rate limited)",
+            "func (context rate limited)",
+        ]
+    );
+}
+
+#[test]
+fn no_panic() {
+    struct Panicky;
+
+    impl Display for Panicky {
+        fn fmt(&self, _: &mut Formatter<'_>) -> std::fmt::Result {
+            panic!("display panicked")
+        }
+    }
+
+    #[errify(no_panic, "literal {arg} {}", Panicky)]
+    fn literal(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(no_panic, Panicky)]
+    fn expr(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(no_panic, "literal {arg}")]
+    fn ok(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = literal(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("<context formatting panicked>"));
+
+    let err = expr(2).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("<context formatting panicked>"));
+
+    let err = ok(3).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 3"));
+}
+
+#[test]
+fn from_error() {
+    #[errify(from_error)]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(cold, from_error)]
+    fn cold(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("default context of 1"));
+
+    let err = cold(2).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("default context of 2"));
+}
+
+#[test]
+fn expose_inner() {
+    #[errify(expose_inner = func_raw, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    struct Struct(i32);
+
+    impl Struct {
+        #[errify(expose_inner = method_raw, "literal {}", self.0)]
+        fn method(&self) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(self.0))
+        }
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+
+    let err = func_raw(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx, None);
+
+    let err = Struct(2).method().unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 2"));
+
+    let err = Struct(2).method_raw().unwrap_err();
+    assert_eq!(err.cx, None);
+}
+
+#[test]
+fn fn_pointer() {
+    #[errify("first {arg}")]
+    fn first(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify("second {arg}")]
+    fn second(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    // Dispatch table of the wrapped functions
+    let ptr = first as fn(i32) -> Result<i32, ErrorWithContext>;
+    let table: [fn(i32) -> Result<i32, ErrorWithContext>; 2] = [ptr, second];
+
+    let err = table[0](1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("first 1"));
+
+    let err = table[1](2).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("second 2"));
+}
+
+#[test]
+fn format_with() {
+    fn brackets(cx: &str) -> String {
+        format!("[{cx}]")
+    }
+
+    #[errify(format_with = brackets, "raw {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(format_with = |cx: &str| cx.to_uppercase(), ContextExpr::new(arg))]
+    fn closure(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("[raw 1]"));
+
+    let err = closure(2).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("CONTEXTEXPR(2)"));
+}
+
+#[test]
+fn const_context() {
+    mod messages {
+        pub const LOAD_FAILED: &str = "Failed to load {id}";
+    }
+
+    #[errify(messages::LOAD_FAILED)]
+    fn func(id: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(id))
+    }
+
+    // The constant is the whole context, its braces are not placeholders
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("Failed to load {id}"));
+}
+
+#[test]
+fn assert_error() {
+    #[errify(assert_error, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[test]
+fn raw_string_and_escaped_braces() {
+    struct Struct {
+        id: i32,
+    }
+
+    #[errify(r"path\to\{arg} {{literal}}")]
+    fn raw(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(eager_args, r#"{{"{arg}"}} {{}} {}"#, arg + 1)]
+    fn eager(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    impl Struct {
+        #[errify("{{self.id}} = {self.id}, {{{self.id}}}")]
+        fn fields(&self) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(self.id))
+        }
+    }
+
+    let err = raw(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some(r"path\to\1 {literal}"));
+
+    let err = eager(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some(r#"{"1"} {} 2"#));
+
+    let err = Struct { id: 3 }.fields().unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("{self.id} = 3, {3}"));
+}
+
+#[test]
+fn post() {
+    // The context is already attached when the result is post-processed
+    fn fallback(res: Result<i32, ErrorWithContext>) -> Result<i32, ErrorWithContext> {
+        match res {
+            Err(err) if err.cx.as_deref() == Some("literal 0") => Ok(-1),
+            res => res,
+        }
+    }
+
+    #[errify(post = fallback, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        match arg {
+            1 => Ok(arg),
+            _ => Err(ErrorWithContext::new(arg)),
+        }
+    }
+
+    assert_eq!(func(1).unwrap(), 1);
+    assert_eq!(func(0).unwrap(), -1);
+
+    let err = func(2).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("literal 2"));
+}
+
+#[test]
+fn rename() {
+    #[errify(rename = func_checked, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx, None);
+
+    let err = func_checked(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[test]
+fn generic_type_name() {
+    #[errify("for type {}", std::any::type_name::<T>())]
+    fn eager<T>(arg: i32) -> Result<T, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(lazy, cold, "for type {}", std::any::type_name::<T>())]
+    fn lazy<T>(arg: i32) -> Result<T, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = eager::<u8>(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("for type u8"));
+
+    let err = lazy::<String>(2).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("for type alloc::string::String"));
+}
+
+#[tokio::test]
+async fn tuple_ok_type() {
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> Result<(i32, String), ErrorWithContext> {
+        match arg {
+            0 => Ok((arg, "zero".to_owned())),
+            _ => Err(ErrorWithContext::new(arg)),
+        }
+    }
+
+    #[errify("async {arg}")]
+    async fn async_func(arg: i32) -> Result<(i32, &'static str), ErrorWithContext> {
+        if arg == 0 {
+            return Ok((arg, "zero"));
+        }
+        Err(ErrorWithContext::new(arg))
+    }
+
+    assert_eq!(func(0).unwrap(), (0, "zero".to_owned()));
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+
+    assert_eq!(async_func(0).await.unwrap(), (0, "zero"));
+    let err = async_func(2).await.unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("async 2"));
+}
+
+#[tokio::test]
+async fn deferred_context() {
+    use std::{
+        cell::Cell,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use errify::testing::CallCounter;
+
+    static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+    static CALLS: CallCounter = CallCounter::new();
+
+    fn counted(arg: i32) -> i32 {
+        CALLS.count(arg)
+    }
+
+    // Formatted on error from the copy of the argument, not as mutated in the body
+    #[errify("async {arg} {}", pair.1)]
+    async fn async_func(mut arg: i32, pair: (u8, u8)) -> Result<i32, ErrorWithContext> {
+        arg += 10;
+        Err(ErrorWithContext::new(arg))
+    }
+
+    // Shared references to values without interior mutability can't be changed by the body
+    #[errify("read {path:?} {name} {bytes:?}")]
+    fn read(path: &std::path::Path, name: &str, bytes: &[u8]) -> Result<usize, ErrorWithContext> {
+        Err(ErrorWithContext::new(
+            name.len() + bytes.len() + path.as_os_str().len(),
+        ))
+    }
+
+    // Other references and statics may be changed by the body, so they are formatted before the call
+    #[errify("value {}", value.get())]
+    fn cell(value: &Cell<i32>) -> Result<i32, ErrorWithContext> {
+        value.set(99);
+        Err(ErrorWithContext::new(value.get()))
+    }
+
+    #[errify("attempt {}", ATTEMPTS.load(Ordering::Relaxed))]
+    fn attempt() -> Result<usize, ErrorWithContext> {
+        let attempt = ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+        Err(ErrorWithContext::new(attempt))
+    }
+
+    // Opt in to format any context on error only
+    #[errify(lazy, "literal {arg} {}", counted(arg))]
+    fn func(arg: i32, name: &str) -> Result<i32, ErrorWithContext> {
+        if name.is_empty() {
+            return Err(ErrorWithContext::new(arg));
+        }
+        Ok(arg)
+    }
+
+    assert_eq!(
+        async_func(2, (0, 3)).await.unwrap_err().cx.as_deref(),
+        Some("async 2 3")
+    );
+    assert_eq!(
+        read("a.txt".as_ref(), "name", &[1, 2])
+            .unwrap_err()
+            .cx
+            .as_deref(),
+        Some(r#"read "a.txt" name [1, 2]"#)
+    );
+    assert_eq!(
+        cell(&Cell::new(1)).unwrap_err().cx.as_deref(),
+        Some("value 1")
+    );
+    assert_eq!(attempt().unwrap_err().cx.as_deref(), Some("attempt 0"));
+
+    assert_eq!(func(1, "name").unwrap(), 1);
+    assert_eq!(CALLS.calls(), 0);
+    assert_eq!(func(1, "").unwrap_err().cx.as_deref(), Some("literal 1 1"));
+    assert_eq!(CALLS.calls(), 1);
+}
+
+#[test]
+fn non_copy_context_args() {
+    // Arguments moved into the body are formatted before the call
+    #[errify("literal {name} {}", items.len())]
+    fn func(name: String, items: Vec<i32>) -> Result<(String, Vec<i32>), ErrorWithContext> {
+        if items.is_empty() {
+            return Err(ErrorWithContext::new(name));
+        }
+        Ok((name, items))
+    }
+
+    assert_eq!(
+        func("name".to_owned(), vec![1]).unwrap(),
+        ("name".to_owned(), vec![1])
+    );
+    let err = func("name".to_owned(), Vec::new()).unwrap_err();
+    assert_eq!(err.msg.deref(), "name");
+    assert_eq!(err.cx.as_deref(), Some("literal name 0"));
+}
+
+#[test]
+fn template() {
+    struct Client {
+        host: &'static str,
+    }
+
+    impl Client {
+        #[errify(
+            template,
+            "GET ${self.host}/${path} failed after {} retries, cost $$${cost:.2}",
+            retries
+        )]
+        fn get(&self, path: &str, retries: u32, cost: f64) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(path))
+        }
+    }
+
+    let client = Client {
+        host: "example.com",
+    };
+    let err = client.get("users", 3, 1.5).unwrap_err();
+    assert_eq!(err.msg.deref(), "users");
+    assert_eq!(
+        err.cx.as_deref(),
+        Some("GET example.com/users failed after 3 retries, cost $1.50")
+    );
+}
+
+#[test]
+fn boxed_error() {
+    fn inner(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> Result<i32, Box<ErrorWithContext>> {
+        Ok(inner(arg)?)
+    }
+
+    #[errify(lazy, "lazy {arg}")]
+    fn lazy(arg: i32) -> Result<i32, Box<ErrorWithContext>> {
+        Err(Box::new(ErrorWithContext::new(arg)))
+    }
+
+    let err: Box<ErrorWithContext> = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+
+    #[errify(cold, "cold {arg}")]
+    fn cold(arg: i32) -> Result<i32, Box<ErrorWithContext>> {
+        Err(Box::new(ErrorWithContext::new(arg)))
+    }
+
+    let err = lazy(2).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("lazy 2"));
+
+    let err = cold(3).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("cold 3"));
+}
+
+#[test]
+fn boxed_error_own_impl() {
+    use std::fmt::Display;
+
+    use errify::WrapErr;
+
+    // Only the box implements `WrapErr`, which doesn't conflict with the crate
+    #[derive(Debug)]
+    struct BoxedError(Vec<String>);
+
+    impl WrapErr for Box<BoxedError> {
+        fn wrap_err<C>(mut self, context: C) -> Self
+        where
+            C: Display + Send + Sync + 'static,
+        {
+            self.0.push(context.to_string());
+            self
+        }
+    }
+
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> Result<i32, Box<BoxedError>> {
+        Err(Box::new(BoxedError(vec![arg.to_string()])))
+    }
+
+    assert_eq!(func(1).unwrap_err().0, ["1", "literal 1"]);
+}
+
+#[test]
+fn hook() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn hook(cx: &str) {
+        assert_eq!(cx, "literal 1");
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[errify(hook = hook, "literal {arg}")]
+    fn func(arg: i32, fail: bool) -> Result<i32, ErrorWithContext> {
+        if fail {
+            Err(ErrorWithContext::new(arg))
+        } else {
+            Ok(arg)
+        }
+    }
+
+    assert_eq!(func(1, false).unwrap(), 1);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let err = func(1, true).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn telemetry() {
+    use std::sync::Mutex;
+
+    static RECORDS: Mutex<Vec<(String, bool)>> = Mutex::new(Vec::new());
+
+    fn record(cx: &str, success: bool) {
+        RECORDS.lock().unwrap().push((cx.to_owned(), success));
+    }
+
+    #[errify(telemetry = record, "op {arg}")]
+    fn func(arg: i32, fail: bool) -> Result<i32, ErrorWithContext> {
+        if fail {
+            Err(ErrorWithContext::new(arg))
+        } else {
+            Ok(arg)
+        }
+    }
+
+    #[errify(field = 1, telemetry = record, "tuple {arg}")]
+    fn tuple(arg: i32) -> ((), Result<i32, ErrorWithContext>) {
+        ((), Err(ErrorWithContext::new(arg)))
+    }
+
+    assert_eq!(func(1, false).unwrap(), 1);
+
+    let err = func(2, true).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("op 2"));
+
+    let err = tuple(3).1.unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("tuple 3"));
+
+    assert_eq!(
+        *RECORDS.lock().unwrap(),
+        [
+            ("op 1".to_owned(), true),
+            ("op 2".to_owned(), false),
+            ("tuple 3".to_owned(), false),
+        ]
+    );
+}
+
+#[test]
+fn format_args_in_spec() {
+    #[errify("literal {:.*} {:>2$} {0:>width$} {{}}", 1, 1.55, arg, width = 3)]
+    fn func(arg: usize) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(2).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("literal 1.6  2   1 {}"));
+}
+
+#[test]
+fn indexed_format_args() {
+    #[errify("literal {0} then {1}, {0}", first, second)]
+    fn indexed(first: i32, second: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(first + second))
+    }
+
+    #[errify("literal {1} {name} {0} {first}", first, second, name = "named")]
+    fn mixed(first: i32, second: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(first + second))
+    }
+
+    #[errify(
+        eager_args,
+        "literal {1} {name} {0} {first}",
+        first,
+        second,
+        name = "named"
+    )]
+    fn eager(first: i32, second: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(first + second))
+    }
+
+    let err = indexed(1, 2).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 1 then 2, 1"));
+
+    let err = mixed(1, 2).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 2 named 1 1"));
+
+    let err = eager(1, 2).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 2 named 1 1"));
+}
+
+#[test]
+fn inner_attributes() {
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        #![allow(unused_variables)]
+        let unused = 1;
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_event() {
+    use tracing::Level;
+    use utils::tracing_capture::TracingCapture;
+
+    #[errify(tracing::Level::WARN, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let capture = TracingCapture::default();
+    let err = tracing::subscriber::with_default(capture.clone(), || func(1).unwrap_err());
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+
+    assert_eq!(
+        capture.events(),
+        [(Level::WARN, "message = literal 1, error = 1".to_owned())]
+    );
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn metrics_counter() {
+    use utils::metrics_capture::MetricsCapture;
+
+    #[errify(counter = "db_errors", "query {arg} failed")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        match arg {
+            0 => Ok(arg),
+            _ => Err(ErrorWithContext::new(arg)),
+        }
+    }
+
+    let capture = MetricsCapture::default();
+    metrics::with_local_recorder(&capture, || {
+        assert_eq!(func(0).unwrap(), 0);
+        let err = func(1).unwrap_err();
+        assert_eq!(err.msg.deref(), "1");
+        assert_eq!(err.cx.as_deref(), Some("query 1 failed"));
+    });
+
+    assert_eq!(
+        capture.counters(),
+        [("db_errors{context=query 1 failed}".to_owned(), 1)]
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn task_local() {
+    tokio::task_local! {
+        static REQUEST_ID: &'static str;
+    }
+
+    #[errify(task_local = REQUEST_ID, "literal {arg}")]
+    async fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = REQUEST_ID.scope("req-42", func(1)).await.unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("[req-42] literal 1"));
+
+    // Not set for the current task
+    let err = func(2).await.unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 2"));
+}
+
+#[cfg(feature = "stream")]
+#[tokio::test]
+async fn stream() {
+    use std::{
+        pin::{pin, Pin},
+        task::{Context, Poll},
+    };
+
+    use futures_core::Stream;
+
+    struct Iter<I>(I);
+
+    impl<I: Iterator + Unpin> Stream for Iter<I> {
+        type Item = I::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<I::Item>> {
+            Poll::Ready(self.0.next())
+        }
+    }
+
+    #[errify(stream, "stream {arg}")]
+    fn func(arg: i32) -> impl Stream<Item = Result<i32, ErrorWithContext>> {
+        Iter((arg..arg + 3).map(|i| match i % 2 {
+            0 => Ok(i),
+            _ => Err(ErrorWithContext::new(i)),
+        }))
+    }
+
+    let mut stream = pin!(func(1));
+    let mut items = Vec::new();
+    while let Some(item) = std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+        items.push(item.map_err(|err| (err.msg.to_string(), err.cx)));
+    }
+    assert_eq!(
+        items,
+        [
+            Err(("1".to_owned(), Some("stream 1".to_owned()))),
+            Ok(2),
+            Err(("3".to_owned(), Some("stream 1".to_owned()))),
+        ]
+    );
+}
+
+#[test]
+fn explicit_return() {
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        if arg == 0 {
+            return Ok(arg);
+        }
+        if arg == 1 {
+            return Err(ErrorWithContext::new(arg));
+        }
+        Ok(arg)
+    }
+
+    assert_eq!(func(0).unwrap(), 0);
+    assert_eq!(func(2).unwrap(), 2);
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[test]
+fn loop_and_labeled_block_tail() {
+    use errify::ContextError;
+
+    #[errify("literal {arg}")]
+    fn loop_tail(arg: i32) -> Result<i32, ContextError<ErrorWithContext>> {
+        let mut i = 0;
+        loop {
+            i += 1;
+            if i == arg {
+                break Err(ErrorWithContext::new(i).into());
+            }
+            if i > 2 {
+                break Ok(i);
+            }
+        }
+    }
+
+    #[errify("literal {arg}")]
+    fn labeled_tail(arg: i32) -> Result<i32, ContextError<ErrorWithContext>> {
+        'block: {
+            if arg == 1 {
+                break 'block Err(ErrorWithContext::new(arg).into());
+            }
+            Ok(arg)
+        }
+    }
+
+    assert_eq!(loop_tail(0).unwrap(), 3);
+    assert_eq!(labeled_tail(0).unwrap(), 0);
+
+    // The yielded error is wrapped once
+    let err = loop_tail(1).unwrap_err();
+    assert_eq!(err.error().msg.deref(), "1");
+    assert_eq!(err.contexts().collect::<Vec<_>>(), ["literal 1"]);
+
+    let err = labeled_tail(1).unwrap_err();
+    assert_eq!(err.error().msg.deref(), "1");
+    assert_eq!(err.contexts().collect::<Vec<_>>(), ["literal 1"]);
+}
+
+#[test]
+fn nested_fn() {
+    fn outer(arg: i32) -> Result<i32, ErrorWithContext> {
+        #[errify("inner {arg}")]
+        fn inner(arg: i32) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+
+        inner(arg + 1)
+    }
+
+    let err = outer(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("inner 2"));
+}
+
+#[tokio::test]
+async fn nested_async_fn() {
+    async fn outer(arg: i32) -> Result<i32, ErrorWithContext> {
+        #[errify("inner {arg}")]
+        async fn inner(arg: i32) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+
+        inner(arg + 1).await
+    }
+
+    let err = outer(1).await.unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("inner 2"));
+}
+
+#[tokio::test]
+async fn nested_async_block() {
+    #[errify("outer {arg}")]
+    async fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        // The nested block and the spawned task have their own futures awaited in the body
+        let doubled = async {
+            let value: Result<i32, ErrorWithContext> = Ok(arg * 2);
+            value
+        }
+        .await?;
+        let spawned = tokio::spawn(async move {
+            if doubled > 2 {
+                Err(ErrorWithContext::new(doubled))
+            } else {
+                Ok(doubled)
+            }
+        })
+        .await
+        .unwrap()?;
+        Ok(spawned)
+    }
+
+    assert_eq!(func(1).await.unwrap(), 2);
+
+    let err = func(2).await.unwrap_err();
+    assert_eq!(err.msg.deref(), "4");
+    assert_eq!(err.cx.as_deref(), Some("outer 2"));
+}
+
+#[test]
+fn format_specs() {
+    #[errify(
+        "literal [{val:>8.2}] [{val:<8.1}] [{val:*^9.3}] [{:$>5}] [{:+}]",
+        arg,
+        arg
+    )]
+    fn func(val: f64, arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1.2345, 1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(
+        err.cx.as_deref(),
+        Some("literal [    1.23] [1.2     ] [**1.234**] [$$$$1] [+1]")
+    );
+}
+
+#[test]
+fn format_debug_specs() {
+    #[derive(Debug)]
+    struct Struct {
+        field: i32,
+    }
+
+    #[errify("literal {arg:?} {arg:#?}")]
+    fn func(arg: Struct) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg.field))
+    }
+
+    let err = func(Struct { field: 1 }).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(
+        err.cx.as_deref(),
+        Some("literal Struct { field: 1 } Struct {\n    field: 1,\n}")
+    );
+}
+
+#[test]
+fn eager_args() {
+    #[errify(eager_args, "literal {arg} {} {named}", other, named = arg * 10)]
+    fn func(mut arg: i32, mut other: String) -> Result<i32, ErrorWithContext> {
+        arg += 1;
+        other.push_str(" changed");
+        Err(ErrorWithContext::new(format!("{arg} {other}")))
+    }
+
+    let err = func(1, "other".to_owned()).unwrap_err();
+    assert_eq!(err.msg.deref(), "2 other changed");
+    assert_eq!(err.cx.as_deref(), Some("literal 1 other 10"));
+}
+
+#[test]
+fn eager_args_method() {
+    #[derive(Debug)]
+    struct Struct;
+
+    impl Struct {
+        #[errify(eager_args, "literal self = {self:?}, arg = {:>5}", arg)]
+        fn func(&self, arg: String) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+    }
+
+    let err = Struct.func("arg".to_owned()).unwrap_err();
+    assert_eq!(err.msg.deref(), "arg");
+    assert_eq!(
+        err.cx.as_deref(),
+        Some("literal self = Struct, arg =   arg")
+    );
+}
+
+#[test]
+fn mut_arg() {
+    #[errify("literal {arg}")]
+    fn func(mut arg: i32) -> Result<i32, ErrorWithContext> {
+        arg += 1;
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[test]
+fn associated_type_return() {
+    trait Trait {
+        type Output;
+
+        fn func(&self, arg: i32) -> Self::Output;
+    }
+
+    struct Struct;
+
+    impl Trait for Struct {
+        type Output = Result<i32, ErrorWithContext>;
+
+        #[errify("literal {arg}")]
+        fn func(&self, arg: i32) -> Self::Output {
+            Err(ErrorWithContext::new(arg))
+        }
+    }
+
+    let err = Struct.func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[tokio::test]
+async fn async_associated_type_return() {
+    trait Trait {
+        type Output;
+
+        async fn func(&self, arg: i32) -> Self::Output;
+    }
+
+    struct Struct;
+
+    impl Trait for Struct {
+        type Output = Result<i32, ErrorWithContext>;
+
+        #[errify("literal {arg}")]
+        async fn func(&self, arg: i32) -> Self::Output {
+            Err(ErrorWithContext::new(arg))
+        }
+    }
+
+    let err = Struct.func(1).await.unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[test]
+fn nested_result() {
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> Result<Result<i32, ErrorWithContext>, ErrorWithContext> {
+        match arg {
+            0 => Ok(Err(ErrorWithContext::new(arg))),
+            _ => Err(ErrorWithContext::new(arg)),
+        }
+    }
+
+    let inner = func(0).unwrap().unwrap_err();
+    assert_eq!(inner.msg.deref(), "0");
+    assert_eq!(inner.cx, None);
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[test]
+fn same_ok_and_err_type() {
+    #[errify("literal {ok}")]
+    fn func<E: errify::WrapErr>(ok: bool, val: E) -> Result<E, E> {
+        if ok {
+            Ok(val)
+        } else {
+            Err(val)
+        }
+    }
+
+    let val = func(true, ErrorWithContext::new(1)).unwrap();
+    assert_eq!(val.msg.deref(), "1");
+    assert_eq!(val.cx, None);
+
+    let err = func(false, ErrorWithContext::new(1)).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal false"));
+}
+
+#[test]
+fn generic_error_type() {
+    // The error type is the generic parameter, it only needs to implement `WrapErr`
+    #[errify("literal {s}")]
+    fn func<T, E>(s: &str, new_err: fn(String) -> E) -> Result<T, E>
+    where
+        T: From<i32>,
+        E: errify::WrapErr,
+    {
+        let v = s.parse::<i32>().map_err(|err| new_err(err.to_string()))?;
+        Ok(T::from(v))
+    }
+
+    assert_eq!(func::<i64, _>("1", ErrorWithContext::new).unwrap(), 1);
+
+    let err = func::<i64, _>("x", ErrorWithContext::new).unwrap_err();
+    assert_eq!(err.msg.deref(), "invalid digit found in string");
+    assert_eq!(err.cx.as_deref(), Some("literal x"));
+}
+
+#[allow(dead_code, unused_macros)]
+mod shadowed_prelude {
+    use std::ops::Deref;
+
+    use errify::{errify, errify_with};
+
+    use super::ErrorWithContext;
+
+    type Result<T> = std::result::Result<T, ErrorWithContext>;
+    struct Ok;
+    struct Err;
+    struct Some;
+    struct None;
+    struct Option;
+    struct Box;
+    struct ToString;
+    macro_rules! format {
+        ($($t:tt)*) => {
+            compile_error!("shadowed format")
+        };
+    }
+    macro_rules! format_args {
+        ($($t:tt)*) => {
+            compile_error!("shadowed format_args")
+        };
+    }
+
+    #[errify("literal {arg}")]
+    fn literal(arg: i32) -> Result<i32> {
+        std::result::Result::Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(cold, hook = |_| (), eager_args, "eager {arg}")]
+    fn options(arg: i32) -> Result<i32> {
+        std::result::Result::Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify_with(skip_empty, || "closure")]
+    fn closure(arg: i32) -> Result<i32> {
+        std::result::Result::Err(ErrorWithContext::new(arg))
+    }
+
+    #[test]
+    fn shadowed_prelude() {
+        let err = literal(1).unwrap_err();
+        assert_eq!(err.msg.deref(), "1");
+        assert_eq!(err.cx.as_deref(), std::option::Option::Some("literal 1"));
+
+        let err = options(1).unwrap_err();
+        assert_eq!(err.cx.as_deref(), std::option::Option::Some("eager 1"));
+
+        let err = closure(1).unwrap_err();
+        assert_eq!(err.cx.as_deref(), std::option::Option::Some("closure"));
+    }
+}
+
+#[test]
+fn boxed_unsized_ok() {
+    trait Trait {
+        fn value(&self) -> i32;
+    }
+
+    impl Trait for i32 {
+        fn value(&self) -> i32 {
+            *self
+        }
+    }
+
+    #[errify("literal {arg}")]
+    fn slice(arg: i32) -> Result<Box<[u8]>, ErrorWithContext> {
+        if arg == 0 {
+            return Ok(Box::new([1, 2]));
+        }
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify("literal {arg}")]
+    fn dyn_trait(arg: i32) -> Result<Box<dyn Trait>, ErrorWithContext> {
+        if arg == 0 {
+            return Ok(Box::new(arg));
+        }
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify("literal {arg}")]
+    fn str(arg: i32) -> Result<Box<str>, ErrorWithContext> {
+        match arg {
+            0 => Ok("str".into()),
+            _ => Err(ErrorWithContext::new(arg)),
+        }
+    }
+
+    assert_eq!(*slice(0).unwrap(), [1, 2]);
+    assert_eq!(dyn_trait(0).unwrap().value(), 0);
+    assert_eq!(&*str(0).unwrap(), "str");
+
+    let err = slice(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+    let err = dyn_trait(1).err().unwrap();
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+    let err = str(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+}
+
+#[test]
+fn ctx_arg() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static FORMATS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Request(String);
+
+    impl Display for Request {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            FORMATS.fetch_add(1, Ordering::SeqCst);
+            write!(f, "request {}", self.0)
+        }
+    }
+
+    #[errify(ctx_arg = request)]
+    fn func(request: Request, fail: bool) -> Result<usize, ErrorWithContext> {
+        let _: &Request = request;
+        if fail {
+            Err(ErrorWithContext::new(request.0.len()))
+        } else {
+            Ok(request.0.len())
+        }
+    }
+
+    assert_eq!(func(Request("body".to_owned()), false).unwrap(), 4);
+    assert_eq!(FORMATS.load(Ordering::SeqCst), 0);
+
+    let err = func(Request("body".to_owned()), true).unwrap_err();
+    assert_eq!(err.msg.deref(), "4");
+    assert_eq!(err.cx.as_deref(), Some("request body"));
+    assert_eq!(FORMATS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn signature_is_kept() {
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify("literal {arg}")]
+    fn generic<'a, T: Display>(arg: &'a T) -> Result<&'a T, ErrorWithContext> {
+        Ok(arg)
+    }
+
+    // Other attribute macros and memoization wrappers see the same signature
+    let _: fn(i32) -> Result<i32, ErrorWithContext> = func;
+    let _: for<'a> fn(&'a i32) -> Result<&'a i32, ErrorWithContext> = generic::<i32>;
+}
+
+#[test]
+fn via_custom_trait() {
+    trait MyErr {
+        fn annotate<C: Display>(self, context: C) -> Self;
+    }
+
+    #[derive(Debug)]
+    struct Annotated(Vec<String>);
+
+    impl MyErr for Annotated {
+        fn annotate<C: Display>(mut self, context: C) -> Self {
+            self.0.push(context.to_string());
+            self
+        }
+    }
+
+    #[errify(via = MyErr::annotate, "literal {arg}")]
+    fn func<E: MyErr>(arg: i32, err: E) -> Result<i32, E> {
+        let _ = arg;
+        Err(err)
+    }
+
+    #[errify(via = |err: E, cx| err.annotate(cx), "closure {arg}")]
+    fn closure<E: MyErr>(arg: i32, err: E) -> Result<i32, E> {
+        let _ = arg;
+        Err(err)
+    }
+
+    let err = func(1, Annotated(Vec::new())).unwrap_err();
+    assert_eq!(err.0, ["literal 1"]);
+
+    let err = closure(1, Annotated(Vec::new())).unwrap_err();
+    assert_eq!(err.0, ["closure 1"]);
+}
+
+#[test]
+fn constant_literal_is_borrowed() {
+    use std::{any::Any, borrow::Cow};
+
+    #[derive(Debug)]
+    struct BorrowedError {
+        cx: Option<&'static str>,
+    }
+
+    impl errify::WrapErr for BorrowedError {
+        fn wrap_err<C>(mut self, context: C) -> Self
+        where
+            C: Display + Send + Sync + 'static,
+        {
+            if let Some(Cow::Borrowed(cx)) =
+                (&context as &dyn Any).downcast_ref::<Cow<'static, str>>()
+            {
+                self.cx = Some(cx);
+            }
+            self
+        }
+    }
+
+    #[errify("constant message")]
+    fn func() -> Result<i32, BorrowedError> {
+        Err(BorrowedError { cx: None })
+    }
+
+    let first = func().unwrap_err().cx.unwrap();
+    let second = func().unwrap_err().cx.unwrap();
+    assert_eq!(first, "constant message");
+    assert_eq!(first.as_ptr(), second.as_ptr());
+}
+
+#[test]
+fn lazy() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static FORMATS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counted(i32);
+
+    impl Display for Counted {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            FORMATS.fetch_add(1, Ordering::SeqCst);
+            write!(f, "{}", self.0)
+        }
+    }
+
+    #[errify(ref(value), lazy, "literal {value} {}", value.0 + 1)]
+    fn func(value: Counted, fail: bool) -> Result<i32, ErrorWithContext> {
+        if fail {
+            Err(ErrorWithContext::new(value.0))
+        } else {
+            Ok(value.0)
+        }
+    }
+
+    assert_eq!(func(Counted(1), false).unwrap(), 1);
+    assert_eq!(FORMATS.load(Ordering::SeqCst), 0);
+
+    let err = func(Counted(1), true).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1 2"));
+    assert_eq!(FORMATS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn block_context() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COMPUTES: AtomicUsize = AtomicUsize::new(0);
+
+    fn compute(arg: i32) -> i32 {
+        COMPUTES.fetch_add(1, Ordering::SeqCst);
+        arg * 2
+    }
+
+    #[errify({
+        let x = compute(arg);
+        format!("block {arg} {x}")
+    })]
+    fn eager(arg: i32, fail: bool) -> Result<i32, ErrorWithContext> {
+        if fail {
+            return Err(ErrorWithContext::new(arg));
+        }
+        Ok(arg)
+    }
+
+    #[errify(lazy, "block {arg} {}", {
+        let x = compute(arg);
+        x + 1
+    })]
+    fn lazy(arg: i32, fail: bool) -> Result<i32, ErrorWithContext> {
+        if fail {
+            return Err(ErrorWithContext::new(arg));
+        }
+        Ok(arg)
+    }
+
+    // The block is evaluated before the body runs
+    assert_eq!(eager(1, false).unwrap(), 1);
+    assert_eq!(COMPUTES.swap(0, Ordering::SeqCst), 1);
+    let err = eager(1, true).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("block 1 2"));
+    assert_eq!(COMPUTES.swap(0, Ordering::SeqCst), 1);
+
+    // The block is evaluated only on error
+    assert_eq!(lazy(1, false).unwrap(), 1);
+    assert_eq!(COMPUTES.swap(0, Ordering::SeqCst), 0);
+    let err = lazy(1, true).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("block 1 3"));
+    assert_eq!(COMPUTES.swap(0, Ordering::SeqCst), 1);
+}
+
+#[test]
+fn lazy_method_call_args() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Items(Vec<i32>);
+
+    impl Items {
+        fn len(&self) -> usize {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            self.0.len()
+        }
+    }
+
+    #[errify(lazy, "processing {} items, sum {}", items.len(), items.0.iter().inspect(|_| {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    }).sum::<i32>())]
+    fn func(items: &Items, fail: bool) -> Result<i32, ErrorWithContext> {
+        if fail {
+            return Err(ErrorWithContext::new(items.0.len()));
+        }
+        Ok(items.0.iter().sum())
+    }
+
+    let items = Items(vec![1, 2, 3]);
+
+    // The method calls are evaluated only on error
+    assert_eq!(func(&items, false).unwrap(), 6);
+    assert_eq!(CALLS.swap(0, Ordering::SeqCst), 0);
+
+    let err = func(&items, true).unwrap_err();
+    assert_eq!(err.msg.deref(), "3");
+    assert_eq!(err.cx.as_deref(), Some("processing 3 items, sum 6"));
+    // `len` once and the iterator once per item
+    assert_eq!(CALLS.swap(0, Ordering::SeqCst), 4);
+}
+
+#[test]
+fn into_boundary_error() {
+    use errify::FromErrorContext;
+
+    #[derive(Debug)]
+    struct BoundaryError {
+        code: i32,
+        context: String,
+    }
+
+    impl FromErrorContext<ErrorWithContext> for BoundaryError {
+        fn from_error_context<C>(error: ErrorWithContext, context: C) -> Self
+        where
+            C: Display + Send + Sync + 'static,
+        {
+            Self {
+                code: error.msg.parse().unwrap(),
+                context: context.to_string(),
+            }
+        }
+    }
+
+    #[errify(into = BoundaryError, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        if arg == 0 {
+            return Ok(arg);
+        }
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(field = 1, into = BoundaryError, "literal {arg}")]
+    fn tuple(arg: i32) -> ((), Result<i32, ErrorWithContext>) {
+        ((), Err(ErrorWithContext::new(arg)))
+    }
+
+    let _: fn(i32) -> Result<i32, BoundaryError> = func;
+    assert_eq!(func(0).unwrap(), 0);
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.code, 1);
+    assert_eq!(err.context, "literal 1");
+
+    let err = tuple(2).1.unwrap_err();
+    assert_eq!(err.code, 2);
+    assert_eq!(err.context, "literal 2");
+}
+
+#[test]
+fn result_alias() {
+    use errify::FromErrorContext;
+
+    type Result<T> = std::result::Result<T, ErrorWithContext>;
+
+    #[derive(Debug)]
+    struct BoundaryError(String);
+
+    impl FromErrorContext<ErrorWithContext> for BoundaryError {
+        fn from_error_context<C>(_error: ErrorWithContext, context: C) -> Self
+        where
+            C: Display + Send + Sync + 'static,
+        {
+            Self(context.to_string())
+        }
+    }
+
+    #[errify(assert_error, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(into = BoundaryError, "into {arg}")]
+    fn into(arg: i32) -> Result<i32> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    assert_eq!(func(1).unwrap_err().cx.as_deref(), Some("literal 1"));
+    let _: fn(i32) -> std::result::Result<i32, BoundaryError> = into;
+    assert_eq!(into(2).unwrap_err().0, "into 2");
+}
+
+#[test]
+fn into_ok_type() {
+    use errify::FromErrorContext;
+
+    trait Handler {
+        type Output;
+    }
+
+    struct Service;
+
+    impl Handler for Service {
+        type Output = Result<i32, ErrorWithContext>;
+    }
+
+    #[derive(Debug)]
+    struct BoundaryError(String);
+
+    impl FromErrorContext<ErrorWithContext> for BoundaryError {
+        fn from_error_context<C>(_error: ErrorWithContext, context: C) -> Self
+        where
+            C: Display + Send + Sync + 'static,
+        {
+            Self(context.to_string())
+        }
+    }
+
+    impl Service {
+        #[errify(ok = i32, into = BoundaryError, "handle {arg}")]
+        fn handle(&self, arg: i32) -> <Self as Handler>::Output {
+            if arg == 0 {
+                return Ok(arg);
+            }
+            Err(ErrorWithContext::new(arg))
+        }
+    }
+
+    let _: fn(&Service, i32) -> Result<i32, BoundaryError> = Service::handle;
+    assert_eq!(Service.handle(0).unwrap(), 0);
+    assert_eq!(Service.handle(1).unwrap_err().0, "handle 1");
+}
+
+#[test]
+fn cfg_error_alias() {
+    #[cfg(debug_assertions)]
+    type Error = ErrorWithContext;
+    #[cfg(not(debug_assertions))]
+    type Error = errify::ContextError<ErrorWithContext>;
+
+    type Result<T> = std::result::Result<T, Error>;
+
+    #[errify(err = Error, "literal {arg}")]
+    fn func(arg: i32) -> Result<i32> {
+        Err(ErrorWithContext::new(arg))?
+    }
+
+    #[errify(lazy, err = Error, "lazy {arg}")]
+    fn lazy(arg: i32) -> Result<i32> {
+        func(arg)
+    }
+
+    fn contexts(err: &Error) -> Vec<String> {
+        #[cfg(debug_assertions)]
+        return err.cx.iter().cloned().collect();
+        #[cfg(not(debug_assertions))]
+        return err.contexts().map(ToOwned::to_owned).collect();
+    }
+
+    assert_eq!(contexts(&func(1).unwrap_err()), ["literal 1"]);
+    if cfg!(debug_assertions) {
+        // The context replaces the previous one
+        assert_eq!(contexts(&lazy(2).unwrap_err()), ["lazy 2"]);
+    } else {
+        assert_eq!(contexts(&lazy(2).unwrap_err()), ["lazy 2", "literal 2"]);
+    }
+}
+
+#[cfg(feature = "anyhow")]
+#[test]
+fn anyhow_result_alias() {
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> anyhow::Result<i32> {
+        anyhow::bail!("error {arg}")
+    }
+
+    #[errify(with_err, |err| format!("with_err {arg}: {err}"))]
+    fn with_err(arg: i32) -> anyhow::Result<i32> {
+        anyhow::bail!("error {arg}")
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.to_string(), "literal 1");
+    assert_eq!(err.root_cause().to_string(), "error 1");
+    assert_eq!(with_err(2).unwrap_err().to_string(), "with_err 2: error 2");
+}
+
+#[test]
+fn factory_returns_fallible_closure() {
+    use errify::ResultExt;
+
+    // Only the error of the factory itself is wrapped, the returned closure is kept as is
+    #[errify("factory {arg}")]
+    fn factory(
+        arg: i32,
+    ) -> Result<impl Fn(i32) -> Result<i32, ErrorWithContext>, ErrorWithContext> {
+        if arg == 0 {
+            return Err(ErrorWithContext::new(arg));
+        }
+        Ok(move |x: i32| match x {
+            0 => Err(ErrorWithContext::new(arg)),
+            _ => Ok(x * arg),
+        })
+    }
+
+    // To wrap the errors of the returned closure, apply the context inside it
+    #[errify("factory {arg}")]
+    fn wrapped(
+        arg: i32,
+    ) -> Result<impl Fn(i32) -> Result<i32, ErrorWithContext>, ErrorWithContext> {
+        let f = factory(arg)?;
+        Ok(move |x: i32| f(x).errify_with(|| format!("closure {x}")))
+    }
+
+    let err = factory(0).err().unwrap();
+    assert_eq!(err.cx.as_deref(), Some("factory 0"));
+
+    let f = factory(2).unwrap();
+    assert_eq!(f(3).unwrap(), 6);
+    let err = f(0).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx, None);
+
+    let f = wrapped(2).unwrap();
+    let err = f(0).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("closure 0"));
+}
+
+#[test]
+fn try_in_nested_closure() {
+    fn parse(s: &str) -> Result<i32, ErrorWithContext> {
+        s.parse().map_err(ErrorWithContext::new)
+    }
+
+    #[errify("literal {}", args.len())]
+    fn func(args: &[&str]) -> Result<Vec<i32>, ErrorWithContext> {
+        let double = |s: &str| -> Result<i32, ErrorWithContext> { Ok(parse(s)? * 2) };
+        let nested = args.iter().map(|s| double(s)).collect::<Vec<_>>();
+        // Errors of the nested closure are not wrapped until returned from the function
+        assert!(nested
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .all(|e| e.cx.is_none()));
+        nested.into_iter().collect()
+    }
+
+    assert_eq!(func(&["1", "2"]).unwrap(), [2, 4]);
+
+    let err = func(&["1", "x"]).unwrap_err();
+    assert_eq!(err.msg.deref(), "invalid digit found in string");
+    assert_eq!(err.cx.as_deref(), Some("literal 2"));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_instrument() {
+    use tracing::{instrument, Level};
+    use utils::tracing_capture::TracingCapture;
+
+    // `errify` is expanded first, `instrument` wraps the function with context
+    #[errify("literal {arg}")]
+    #[instrument(err)]
+    fn errify_first(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    // `instrument` is expanded first, `errify` wraps the instrumented function
+    #[instrument(err)]
+    #[errify("literal {arg}")]
+    fn instrument_first(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let capture = TracingCapture::default();
+    let err = tracing::subscriber::with_default(capture.clone(), || errify_first(1).unwrap_err());
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+    assert_eq!(
+        capture.spans(),
+        [("errify_first".to_owned(), "arg = 1".to_owned())]
+    );
+    // The error is recorded with context
+    assert_eq!(
+        capture.events(),
+        [(Level::ERROR, "error = literal 1".to_owned())]
+    );
+
+    let capture = TracingCapture::default();
+    let err =
+        tracing::subscriber::with_default(capture.clone(), || instrument_first(1).unwrap_err());
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+    assert_eq!(
+        capture.spans(),
+        [("instrument_first".to_owned(), "arg = 1".to_owned())]
+    );
+    // The error is recorded before the context is attached
+    assert_eq!(capture.events(), [(Level::ERROR, "error = 1".to_owned())]);
+}
+
+#[test]
+fn const_macros() {
+    #[errify(concat!("literal ", env!("CARGO_PKG_NAME"), " {}"), arg)]
+    fn format_macro(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify("literal {} {} {arg}", env!("CARGO_PKG_NAME"), concat!("a", "b"))]
+    fn arg_macros(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify(concat!("literal ", env!("CARGO_PKG_NAME")))]
+    fn expr_macro(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = format_macro(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal errify 1"));
+
+    let err = arg_macros(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal errify ab 1"));
+
+    let err = expr_macro(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("literal errify"));
+}
+
+#[test]
+fn variant_field() {
+    #[derive(Debug, PartialEq)]
+    enum Error {
+        Io { code: i32, context: Option<String> },
+        Parse { code: i32 },
+    }
+
+    #[errify(Error::Io { context } => "literal {arg}")]
+    fn func(arg: i32) -> Result<i32, Error> {
+        match arg {
+            0 => Ok(arg),
+            1 => Err(Error::Io {
+                code: arg,
+                context: None,
+            }),
+            _ => Err(Error::Parse { code: arg }),
+        }
+    }
+
+    assert_eq!(func(0).unwrap(), 0);
+
+    let err = func(1).unwrap_err();
+    assert_eq!(
+        err,
+        Error::Io {
+            code: 1,
+            context: Some("literal 1".to_owned())
+        }
+    );
+
+    // Other variants are returned as is
+    let err = func(2).unwrap_err();
+    assert_eq!(err, Error::Parse { code: 2 });
+}
+
+#[cfg(feature = "anyhow")]
+#[test]
+fn match_source() {
+    #[errify(match_source {
+        std::io::Error => "io failed for {arg}",
+        std::num::ParseIntError => "parse failed for {arg}",
+    })]
+    fn func(arg: &str) -> Result<i32, anyhow::Error> {
+        match arg {
+            "io" => Err(std::io::Error::other("io error"))?,
+            "other" => Err(anyhow::anyhow!("other error")),
+            _ => Ok(arg.parse::<i32>()?),
+        }
+    }
+
+    #[errify(match_source {
+        std::io::Error => "io failed",
+        _ => "failed for {arg}",
+    })]
+    fn with_default(arg: &str) -> Result<i32, anyhow::Error> {
+        Err(anyhow::anyhow!("other error {arg}"))
+    }
+
+    assert_eq!(func("1").unwrap(), 1);
+
+    let err = func("io").unwrap_err();
+    assert_eq!(err.to_string(), "io failed for io");
+    assert_eq!(err.root_cause().to_string(), "io error");
+
+    let err = func("nan").unwrap_err();
+    assert_eq!(err.to_string(), "parse failed for nan");
+    assert!(err.root_cause().is::<std::num::ParseIntError>());
+
+    // No matching arm, the error is returned as is
+    let err = func("other").unwrap_err();
+    assert_eq!(err.to_string(), "other error");
+    assert_eq!(err.chain().count(), 1);
+
+    let err = with_default("x").unwrap_err();
+    assert_eq!(err.to_string(), "failed for x");
+    assert_eq!(err.root_cause().to_string(), "other error x");
+}
+
+#[test]
+fn with_err() {
+    struct Context {
+        arg: i32,
+        msg: String,
+    }
+
+    impl Context {
+        fn new(arg: i32, err: &ErrorWithContext) -> Self {
+            Self {
+                arg,
+                msg: err.msg.to_string(),
+            }
+        }
+    }
+
+    impl Display for Context {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "context {} for error {}", self.arg, self.msg)
+        }
+    }
+
+    #[errify(with_err, |err| Context::new(arg, err))]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg + 1))
+    }
+
+    #[errify(with_err, cold, |err| format!("cold {arg} {}", err.msg))]
+    fn cold(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg + 1))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "2");
+    assert_eq!(err.cx.as_deref(), Some("context 1 for error 2"));
+
+    let err = cold(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("cold 1 2"));
+}
+
+#[tokio::test]
+async fn boxed_future() {
+    use std::{future::Future, pin::Pin};
+
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> Pin<Box<dyn Future<Output = Result<i32, ErrorWithContext>> + Send>> {
+        Box::pin(async move { Err(ErrorWithContext::new(arg)) })
+    }
+
+    // `#[async_trait]` expansion
+    trait Trait {
+        fn func<'life0, 'async_trait>(
+            &'life0 self,
+            arg: i32,
+        ) -> Pin<Box<dyn Future<Output = Result<i32, ErrorWithContext>> + Send + 'async_trait>>
+        where
+            'life0: 'async_trait,
+            Self: 'async_trait;
+    }
+
+    #[derive(Debug)]
+    struct Struct;
+
+    impl Trait for Struct {
+        #[errify("literal self = {self:?}")]
+        fn func<'life0, 'async_trait>(
+            &'life0 self,
+            arg: i32,
+        ) -> Pin<Box<dyn Future<Output = Result<i32, ErrorWithContext>> + Send + 'async_trait>>
+        where
+            'life0: 'async_trait,
+            Self: 'async_trait,
+        {
+            Box::pin(async move {
+                let _self = self;
+                Err(ErrorWithContext::new(arg))
+            })
+        }
+    }
+
+    let err = func(1).await.unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal 1"));
+
+    let err = Struct.func(1).await.unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal self = Struct"));
+}
+
+#[tokio::test]
+async fn boxed_future_trait_object() {
+    use std::{future::Future, pin::Pin};
+
+    // Object-safe async trait, the method returns the boxed future explicitly
+    trait Store: Send + Sync {
+        fn load<'a>(
+            &'a self,
+            key: &'a str,
+        ) -> Pin<Box<dyn Future<Output = Result<i32, ErrorWithContext>> + Send + 'a>>;
+    }
+
+    struct Memory(i32);
+
+    impl Store for Memory {
+        #[errify("failed to load {key}")]
+        fn load<'a>(
+            &'a self,
+            key: &'a str,
+        ) -> Pin<Box<dyn Future<Output = Result<i32, ErrorWithContext>> + Send + 'a>> {
+            Box::pin(async move {
+                match key {
+                    "value" => Ok(self.0),
+                    _ => Err(ErrorWithContext::new(key)),
+                }
+            })
+        }
+    }
+
+    let store: Box<dyn Store> = Box::new(Memory(1));
+    assert_eq!(store.load("value").await.unwrap(), 1);
+
+    let err = store.load("missing").await.unwrap_err();
+    assert_eq!(err.msg.deref(), "missing");
+    assert_eq!(err.cx.as_deref(), Some("failed to load missing"));
+}
+
+#[tokio::test]
+async fn impl_future_send() {
+    use std::future::Future;
+
+    fn assert_send<F: Future + Send>(fut: F) -> F {
+        fut
+    }
+
+    #[errify("literal {arg:?}")]
+    fn func<T>(arg: T) -> impl Future<Output = Result<T, ErrorWithContext>> + Send
+    where
+        T: std::fmt::Debug + Send,
+    {
+        async move {
+            let _arg = arg;
+            Err(ErrorWithContext::new(1))
+        }
+    }
+
+    trait Trait {
+        fn func(&self, arg: i32) -> impl Future<Output = Result<i32, ErrorWithContext>> + Send;
+    }
+
+    #[derive(Debug)]
+    struct Struct;
+
+    impl Trait for Struct {
+        #[errify("literal self = {self:?}")]
+        fn func(&self, arg: i32) -> impl Future<Output = Result<i32, ErrorWithContext>> + Send {
+            async move { Err(ErrorWithContext::new(arg)) }
+        }
+    }
+
+    // The wrapped future keeps the `Send` bound, e.g. to be spawned
+    let err = tokio::spawn(func("v")).await.unwrap().unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal \"v\""));
+
+    let err = assert_send(Struct.func(1)).await.unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("literal self = Struct"));
+}
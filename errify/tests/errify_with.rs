@@ -202,3 +202,379 @@ fn eyre_error() {
     assert_eq!(context_err, "closure 1 = 1");
     assert_eq!(custom_err, "error 1");
 }
+
+#[tokio::test]
+async fn lazy_not_called_on_success() {
+    use errify::testing::CallCounter;
+
+    static CALLS: CallCounter = CallCounter::new();
+
+    #[errify_with(|| CALLS.count(format!("closure {arg}")))]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        if arg < 0 {
+            return Err(ErrorWithContext::new(arg));
+        }
+        Ok(arg)
+    }
+
+    #[errify_with(cold, || CALLS.count(format!("cold {arg}")))]
+    fn cold(arg: i32) -> Result<i32, ErrorWithContext> {
+        if arg < 0 {
+            return Err(ErrorWithContext::new(arg));
+        }
+        Ok(arg)
+    }
+
+    #[errify_with(|| CALLS.count(format!("async {arg}")))]
+    async fn async_func(arg: i32) -> Result<i32, ErrorWithContext> {
+        if arg < 0 {
+            return Err(ErrorWithContext::new(arg));
+        }
+        Ok(arg)
+    }
+
+    assert_eq!(func(1).unwrap(), 1);
+    assert_eq!(cold(1).unwrap(), 1);
+    assert_eq!(async_func(1).await.unwrap(), 1);
+    assert_eq!(CALLS.calls(), 0);
+
+    assert_eq!(func(-1).unwrap_err().cx.as_deref(), Some("closure -1"));
+    assert_eq!(cold(-1).unwrap_err().cx.as_deref(), Some("cold -1"));
+    assert_eq!(
+        async_func(-1).await.unwrap_err().cx.as_deref(),
+        Some("async -1")
+    );
+    assert_eq!(CALLS.calls(), 3);
+}
+
+#[cfg(feature = "anyhow")]
+#[tokio::test]
+async fn anyhow_lazy_not_called_on_success() {
+    use errify::testing::CallCounter;
+
+    static CALLS: CallCounter = CallCounter::new();
+
+    #[errify_with(|| CALLS.count(format!("closure {arg}")))]
+    fn func(arg: i32) -> Result<i32, anyhow::Error> {
+        anyhow::ensure!(arg >= 0, "error {arg}");
+        Ok(arg)
+    }
+
+    #[errify_with(|| CALLS.count(format!("async {arg}")))]
+    async fn async_func(arg: i32) -> Result<i32, anyhow::Error> {
+        anyhow::ensure!(arg >= 0, "error {arg}");
+        Ok(arg)
+    }
+
+    assert_eq!(func(1).unwrap(), 1);
+    assert_eq!(async_func(1).await.unwrap(), 1);
+    assert_eq!(CALLS.calls(), 0);
+
+    assert_eq!(func(-1).unwrap_err().to_string(), "closure -1");
+    assert_eq!(async_func(-1).await.unwrap_err().to_string(), "async -1");
+    assert_eq!(CALLS.calls(), 2);
+}
+
+#[cfg(feature = "eyre")]
+#[tokio::test]
+async fn eyre_lazy_not_called_on_success() {
+    use errify::testing::CallCounter;
+
+    static CALLS: CallCounter = CallCounter::new();
+
+    #[errify_with(|| CALLS.count(format!("closure {arg}")))]
+    fn func(arg: i32) -> Result<i32, eyre::Report> {
+        eyre::ensure!(arg >= 0, "error {arg}");
+        Ok(arg)
+    }
+
+    #[errify_with(|| CALLS.count(format!("async {arg}")))]
+    async fn async_func(arg: i32) -> Result<i32, eyre::Report> {
+        eyre::ensure!(arg >= 0, "error {arg}");
+        Ok(arg)
+    }
+
+    assert_eq!(func(1).unwrap(), 1);
+    assert_eq!(async_func(1).await.unwrap(), 1);
+    assert_eq!(CALLS.calls(), 0);
+
+    assert_eq!(func(-1).unwrap_err().to_string(), "closure -1");
+    assert_eq!(async_func(-1).await.unwrap_err().to_string(), "async -1");
+    assert_eq!(CALLS.calls(), 2);
+}
+
+#[test]
+fn ref_capture() {
+    #[errify_with(ref(big, other), || format!("closure {} {other}", big.len()))]
+    fn func(big: Vec<u8>, other: String, arg: String) -> Result<i32, ErrorWithContext> {
+        let _: &Vec<u8> = big;
+        let _: &String = other;
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(vec![0; 3], "other".to_owned(), "argument".to_owned()).unwrap_err();
+    assert_eq!(err.msg.deref(), "argument");
+    assert_eq!(err.cx.as_deref(), Some("closure 3 other"));
+}
+
+#[test]
+fn tuple_field() {
+    #[errify_with(field = 0, || format!("closure {arg}"))]
+    fn func(arg: i32) -> (Result<i32, ErrorWithContext>, &'static str) {
+        (Err(ErrorWithContext::new(arg)), "meta")
+    }
+
+    let (res, meta) = func(1);
+    let err = res.unwrap_err();
+    assert_eq!(meta, "meta");
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("closure 1"));
+}
+
+#[test]
+fn cold() {
+    #[errify_with(cold, || format!("closure {arg}"))]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("closure 1"));
+}
+
+#[test]
+fn hook() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    #[errify_with(hook = |cx: &str| { assert_eq!(cx, "closure 1"); CALLS.fetch_add(1, Ordering::SeqCst); }, || format!("closure {arg}"))]
+    fn func(arg: i32, fail: bool) -> Result<i32, ErrorWithContext> {
+        if fail {
+            Err(ErrorWithContext::new(arg))
+        } else {
+            Ok(arg)
+        }
+    }
+
+    assert_eq!(func(1, false).unwrap(), 1);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let err = func(1, true).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("closure 1"));
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
+
+mod config {
+    pub const NAME: &str = "config";
+
+    pub mod nested {
+        pub fn context() -> String {
+            format!("nested {}", crate::config::NAME)
+        }
+    }
+}
+
+#[test]
+fn crate_path_closure() {
+    #[errify_with(|| format!("closure {} {arg}", crate::config::NAME))]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("closure config 1"));
+}
+
+#[test]
+fn crate_path_fn() {
+    pub mod module {
+        use super::*;
+
+        #[errify_with(crate::config::nested::context)]
+        pub fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+    }
+
+    let err = module::func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("nested config"));
+}
+
+#[test]
+fn inner_attributes() {
+    #[errify_with(|| format!("closure {arg}"))]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        #![allow(unused_variables)]
+        let unused = 1;
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("closure 1"));
+}
+
+#[test]
+fn explicit_return() {
+    #[errify_with(|| format!("closure {arg}"))]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        if arg == 0 {
+            return Ok(arg);
+        }
+        if arg == 1 {
+            return Err(ErrorWithContext::new(arg));
+        }
+        Ok(arg)
+    }
+
+    assert_eq!(func(0).unwrap(), 0);
+    assert_eq!(func(2).unwrap(), 2);
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("closure 1"));
+}
+
+#[test]
+fn skip_empty() {
+    #[errify_with(skip_empty, || if arg > 0 { format!("closure {arg}") } else { String::new() })]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    #[errify_with(skip_empty, || "")]
+    fn empty(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("closure 1"));
+
+    let err = func(0).unwrap_err();
+    assert_eq!(err.msg.deref(), "0");
+    assert_eq!(err.cx, None);
+
+    let err = empty(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx, None);
+}
+
+#[test]
+fn const_fn_borrowed_context() {
+    use std::any::Any;
+
+    #[derive(Debug)]
+    struct BorrowedError {
+        cx: Option<&'static str>,
+    }
+
+    impl errify::WrapErr for BorrowedError {
+        fn wrap_err<C>(mut self, context: C) -> Self
+        where
+            C: Display + Send + Sync + 'static,
+        {
+            self.cx = (&context as &dyn Any)
+                .downcast_ref::<&'static str>()
+                .copied();
+            self
+        }
+    }
+
+    const fn context() -> &'static str {
+        "const context"
+    }
+
+    #[errify_with(context)]
+    fn func() -> Result<i32, BorrowedError> {
+        Err(BorrowedError { cx: None })
+    }
+
+    let err = func().unwrap_err();
+    assert_eq!(err.cx, Some("const context"));
+}
+
+#[test]
+fn once_cx() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COMPUTES: AtomicUsize = AtomicUsize::new(0);
+
+    fn context() -> impl Display {
+        COMPUTES.fetch_add(1, Ordering::SeqCst);
+        ContextExpr::new(2)
+    }
+
+    #[errify_with(once_cx, context)]
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        if arg == 0 {
+            return Ok(arg);
+        }
+        Err(ErrorWithContext::new(arg))
+    }
+
+    // The provider is not called without an error
+    assert_eq!(func(0).unwrap(), 0);
+    assert_eq!(COMPUTES.load(Ordering::SeqCst), 0);
+
+    for arg in 1..3 {
+        let err = func(arg).unwrap_err();
+        assert_eq!(err.cx.as_deref(), Some("ContextExpr(2)"));
+    }
+    assert_eq!(COMPUTES.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn wrap_err_with_override() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use errify::{ResultExt, WrapErr};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    // Discards the context, so the lazy context is never evaluated
+    #[derive(Debug)]
+    struct LeanError;
+
+    impl WrapErr for LeanError {
+        fn wrap_err<C>(self, _context: C) -> Self
+        where
+            C: Display + Send + Sync + 'static,
+        {
+            self
+        }
+
+        fn wrap_err_with<C, F>(self, _f: F) -> Self
+        where
+            C: Display + Send + Sync + 'static,
+            F: FnOnce() -> C,
+        {
+            self
+        }
+    }
+
+    fn context() -> impl Display {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        "context"
+    }
+
+    #[errify_with(context)]
+    fn func() -> Result<i32, LeanError> {
+        Err(LeanError)
+    }
+
+    #[errify_with(cold, context)]
+    fn cold() -> Result<i32, LeanError> {
+        Err(LeanError)
+    }
+
+    func().unwrap_err();
+    cold().unwrap_err();
+    Err::<i32, _>(LeanError).errify_with(context).unwrap_err();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,49 @@
+#![cfg(feature = "http")]
+
+use std::error::Error;
+
+use errify::{errify, HttpError};
+use http::{Method, StatusCode, Uri};
+
+#[tokio::test]
+async fn status_kept() {
+    #[errify("{method} {uri} failed")]
+    async fn handler(method: Method, uri: Uri) -> Result<String, HttpError> {
+        Err(HttpError::new(
+            StatusCode::NOT_FOUND,
+            std::io::Error::other(format!("no such page {}", uri.path())),
+        ))
+    }
+
+    let uri = Uri::from_static("/users/1");
+    let err = handler(Method::GET, uri).await.unwrap_err();
+    assert_eq!(err.status(), StatusCode::NOT_FOUND);
+    assert_eq!(err.to_string(), "GET /users/1 failed");
+    assert_eq!(err.source().unwrap().to_string(), "no such page /users/1");
+    assert!(err.error().is::<std::io::Error>());
+}
+
+#[tokio::test]
+async fn status_context() {
+    use errify::StatusContext;
+
+    #[errify("Failed to load user {id}")]
+    async fn load(id: u32) -> Result<String, HttpError> {
+        Err(HttpError::new(StatusCode::NOT_FOUND, "no such user"))
+    }
+
+    #[errify(StatusContext::new(StatusCode::BAD_GATEWAY, format!("{method} {uri} failed")))]
+    async fn handler(method: Method, uri: Uri, id: u32) -> Result<String, HttpError> {
+        load(id).await
+    }
+
+    let uri = Uri::from_static("/users/1");
+    let err = handler(Method::GET, uri, 1).await.unwrap_err();
+    assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(err.to_string(), "GET /users/1 failed");
+    assert_eq!(
+        err.contexts().collect::<Vec<_>>(),
+        ["GET /users/1 failed", "Failed to load user 1"]
+    );
+    assert_eq!(err.error().to_string(), "no such user");
+}
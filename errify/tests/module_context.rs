@@ -0,0 +1,115 @@
+mod utils;
+
+use std::{collections::HashMap, ops::Deref};
+
+use errify::{errify, module_context};
+use utils::*;
+
+mod storage {
+    use super::*;
+
+    module_context!("storage::{fn} failed", {
+        pub fn load(arg: i32) -> Result<i32, ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+
+        pub fn save(arg: i32) -> Result<(), ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+
+        pub async fn remove(arg: i32) -> Result<(), ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+
+        #[errify("custom {arg}")]
+        pub fn custom(arg: i32) -> Result<(), ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+
+        pub fn len(arg: i32) -> usize {
+            arg as usize
+        }
+
+        pub fn index(arg: i32) -> HashMap<String, i32> {
+            HashMap::from([(arg.to_string(), arg)])
+        }
+
+        pub fn r#type(arg: i32) -> Result<(), ErrorWithContext> {
+            Err(ErrorWithContext::new(arg))
+        }
+
+        pub struct Store;
+
+        impl Store {
+            pub fn get(&self, arg: i32) -> Result<i32, ErrorWithContext> {
+                Err(ErrorWithContext::new(arg))
+            }
+        }
+
+        pub trait Backend {
+            fn put(&self, arg: i32) -> Result<(), ErrorWithContext>;
+
+            fn flush(&self, arg: i32) -> Result<(), ErrorWithContext> {
+                Err(ErrorWithContext::new(arg))
+            }
+        }
+
+        impl Backend for Store {
+            fn put(&self, arg: i32) -> Result<(), ErrorWithContext> {
+                Err(ErrorWithContext::new(arg))
+            }
+        }
+
+        pub mod cache {
+            use super::*;
+
+            pub fn evict(arg: i32) -> Result<(), ErrorWithContext> {
+                Err(ErrorWithContext::new(arg))
+            }
+        }
+    });
+}
+
+#[tokio::test]
+async fn name_context() {
+    let err = storage::load(1).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("storage::load failed"));
+
+    let err = storage::save(2).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("storage::save failed"));
+
+    let err = storage::remove(3).await.unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("storage::remove failed"));
+}
+
+#[test]
+fn own_context_and_other_fns() {
+    let err = storage::custom(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("custom 1"));
+
+    assert_eq!(storage::len(2), 2);
+    assert_eq!(storage::index(3)["3"], 3);
+}
+
+#[test]
+fn nested_items() {
+    use storage::Backend;
+
+    let err = storage::r#type(1).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("storage::type failed"));
+
+    let err = storage::Store.get(2).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("storage::get failed"));
+
+    let err = storage::Store.put(3).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("storage::put failed"));
+
+    let err = storage::cache::evict(4).unwrap_err();
+    assert_eq!(err.cx.as_deref(), Some("storage::evict failed"));
+
+    // Default methods of traits are kept as is
+    let err = storage::Store.flush(5).unwrap_err();
+    assert_eq!(err.msg.deref(), "5");
+    assert_eq!(err.cx, None);
+}
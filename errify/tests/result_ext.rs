@@ -0,0 +1,98 @@
+mod utils;
+
+use std::ops::Deref;
+
+use errify::ResultExt;
+use utils::*;
+
+#[test]
+fn errify() {
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let err = func(1).errify(ContextExpr::new(2)).unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("ContextExpr(2)"));
+}
+
+#[test]
+fn errify_with() {
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Err(ErrorWithContext::new(arg))
+    }
+
+    let arg = 1;
+    let err = func(arg)
+        .errify_with(|| format!("closure {arg}"))
+        .unwrap_err();
+    assert_eq!(err.msg.deref(), "1");
+    assert_eq!(err.cx.as_deref(), Some("closure 1"));
+}
+
+#[test]
+fn errify_with_ok() {
+    fn func(arg: i32) -> Result<i32, ErrorWithContext> {
+        Ok(arg)
+    }
+
+    let res = func(1).errify_with(|| -> String { panic!("context must not be evaluated") });
+    assert_eq!(res.unwrap(), 1);
+}
+
+#[cfg(feature = "anyhow")]
+#[test]
+fn anyhow_error() {
+    fn func(arg: i32) -> Result<i32, anyhow::Error> {
+        Err(anyhow::anyhow!("error {}", arg))
+    }
+
+    let err = func(1).errify("context").unwrap_err();
+    assert_eq!(err.to_string(), "context");
+    assert_eq!(err.root_cause().to_string(), "error 1");
+}
+
+#[cfg(feature = "anyhow")]
+#[test]
+fn anyhow_wrap_err_src() {
+    use std::{
+        error::Error,
+        fmt::{Display, Formatter},
+    };
+
+    use errify::WrapErrSrc;
+
+    #[derive(Debug)]
+    struct RichContext(Option<std::io::Error>);
+
+    impl Display for RichContext {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            f.write_str("rich context")
+        }
+    }
+
+    impl Error for RichContext {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.0.as_ref().map(|e| e as _)
+        }
+    }
+
+    let parse_err = "1x".parse::<i32>().unwrap_err();
+    let err = anyhow::Error::new(parse_err.clone())
+        .wrap_err_src(RichContext(Some(std::io::Error::other("io error"))));
+    let chain = err.chain().map(|e| e.to_string()).collect::<Vec<_>>();
+    assert_eq!(chain, ["rich context", "io error", &parse_err.to_string()]);
+    assert!(err.root_cause().is::<std::num::ParseIntError>());
+
+    // Both the context and the wrapped error are kept
+    assert!(err.downcast_ref::<RichContext>().is_some());
+    assert_eq!(
+        err.downcast_ref::<std::num::ParseIntError>(),
+        Some(&parse_err)
+    );
+
+    // Without a source of its own, the context is followed by the wrapped error
+    let err = anyhow::anyhow!("error 1").wrap_err_src(RichContext(None));
+    let chain = err.chain().map(|e| e.to_string()).collect::<Vec<_>>();
+    assert_eq!(chain, ["rich context", "error 1"]);
+}
@@ -0,0 +1,40 @@
+use std::io;
+
+use errify::{errify, testing::strip_context, ContextError};
+
+#[test]
+fn strip_context_roundtrip() {
+    #[errify("literal {arg}")]
+    fn func(arg: i32) -> Result<i32, ContextError<io::Error>> {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("error {arg}"),
+        ))?
+    }
+
+    let err = func(1).unwrap_err();
+    assert_eq!(err.to_string(), "literal 1");
+    assert_eq!(err.contexts().collect::<Vec<_>>(), ["literal 1"]);
+
+    let err = strip_context(err);
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(err.to_string(), "error 1");
+}
+
+#[test]
+fn strip_context_layers() {
+    #[errify("inner {arg}")]
+    fn inner(arg: i32) -> Result<i32, ContextError<io::Error>> {
+        Err(io::Error::other(format!("error {arg}")))?
+    }
+
+    #[errify("outer {arg}")]
+    fn outer(arg: i32) -> Result<i32, ContextError<io::Error>> {
+        inner(arg)
+    }
+
+    let err = outer(1).unwrap_err();
+    assert_eq!(err.to_string(), "outer 1");
+    assert_eq!(err.contexts().collect::<Vec<_>>(), ["outer 1", "inner 1"]);
+    assert_eq!(strip_context(err).to_string(), "error 1");
+}
@@ -0,0 +1,13 @@
+// Diagnostics of trait errors list the implementors, which depend on enabled features,
+// so UI tests are run with the default features only.
+#[cfg(not(any(
+    feature = "anyhow",
+    feature = "eyre",
+    feature = "snafu",
+    feature = "http"
+)))]
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
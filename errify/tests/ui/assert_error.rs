@@ -0,0 +1,22 @@
+use std::fmt::Display;
+
+use errify::{errify, WrapErr};
+
+#[derive(Debug)]
+struct NotAnError;
+
+impl WrapErr for NotAnError {
+    fn wrap_err<C>(self, _context: C) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        self
+    }
+}
+
+#[errify(assert_error, "context")]
+fn func() -> Result<(), NotAnError> {
+    Err(NotAnError)
+}
+
+fn main() {}
@@ -0,0 +1,31 @@
+use errify::{errify, ContextError};
+
+trait Handler {
+    type Output;
+
+    fn handle(&self) -> Self::Output;
+}
+
+struct Counter;
+
+impl Handler for Counter {
+    type Output = usize;
+
+    #[errify("Failed to count")]
+    fn handle(&self) -> Self::Output {
+        1
+    }
+}
+
+struct Loader;
+
+impl Handler for Loader {
+    type Output = Result<String, ContextError<std::io::Error>>;
+
+    #[errify("Failed to load")]
+    fn handle(&self) -> Self::Output {
+        Ok(String::new())
+    }
+}
+
+fn main() {}
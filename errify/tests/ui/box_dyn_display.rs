@@ -0,0 +1,8 @@
+use errify::errify;
+
+#[errify("context")]
+fn func() -> Result<(), Box<dyn std::fmt::Display + Send + Sync>> {
+    unimplemented!()
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use errify::errify;
+
+struct CustomError;
+
+#[errify("context")]
+fn func() -> Result<(), Box<CustomError>> {
+    unimplemented!()
+}
+
+fn main() {}
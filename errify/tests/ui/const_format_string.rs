@@ -0,0 +1,12 @@
+use errify::errify;
+
+mod messages {
+    pub const LOAD_FAILED: &str = "Failed to load {}";
+}
+
+#[errify(messages::LOAD_FAILED, id)]
+fn load(id: u32) -> Result<(), std::io::Error> {
+    unimplemented!()
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use errify::errify;
+
+#[errify(field = 1, "context")]
+fn func() -> Result<(), std::io::Error> {
+    unimplemented!()
+}
+
+#[errify(field = 2, "context")]
+fn func_out_of_range() -> ((), Result<(), std::io::Error>) {
+    unimplemented!()
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use errify::errify;
+
+struct BoundaryError;
+
+type Res = Result<i32, errify::ContextError<std::io::Error>>;
+
+#[errify(into = BoundaryError, "context")]
+fn func() -> Res {
+    Ok(1)
+}
+
+fn main() {}
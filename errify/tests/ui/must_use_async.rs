@@ -0,0 +1,18 @@
+#![deny(unused_must_use)]
+
+use errify::{errify, ContextError};
+
+#[errify("context {arg}")]
+#[must_use = "the loaded value must be used"]
+async fn load(arg: i32) -> Result<i32, ContextError<std::io::Error>> {
+    Ok(arg)
+}
+
+async fn caller() {
+    load(1);
+    let _ = load(2).await;
+}
+
+fn main() {
+    drop(caller());
+}
@@ -0,0 +1,9 @@
+use errify::{errify, errify_with};
+
+#[errify("context")]
+struct Struct;
+
+#[errify_with(|| "context")]
+type Alias = fn() -> Result<(), std::io::Error>;
+
+fn main() {}
@@ -0,0 +1,19 @@
+use errify::errify;
+
+#[errify(once_cx, || format!("Failed to connect to {host}"))]
+fn connect(host: &str) -> Result<(), errify::ContextError<std::io::Error>> {
+    unimplemented!()
+}
+
+struct Client {
+    host: String,
+}
+
+impl Client {
+    #[errify(once_cx, || self.host.clone())]
+    fn request(&self) -> Result<(), errify::ContextError<std::io::Error>> {
+        unimplemented!()
+    }
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use errify::errify;
+
+#[errify("context")]
+fn func() -> Option<i32> {
+    None
+}
+
+#[errify(field = 1, "context")]
+fn tuple() -> (i32, Option<i32>) {
+    (1, None)
+}
+
+fn main() {}
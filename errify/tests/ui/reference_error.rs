@@ -0,0 +1,11 @@
+use errify::errify;
+
+#[derive(Debug)]
+struct ErrKind;
+
+#[errify("context")]
+fn func<'a>() -> Result<(), &'a ErrKind> {
+    unimplemented!()
+}
+
+fn main() {}
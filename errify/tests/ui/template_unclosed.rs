@@ -0,0 +1,8 @@
+use errify::errify;
+
+#[errify(template, "Failed to read ${path")]
+fn read(path: &str) -> Result<String, errify::ContextError<std::io::Error>> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use errify::errify;
+
+fn build() -> Result<String, std::io::Error> {
+    Ok("context".to_owned())
+}
+
+#[errify(build()?)]
+fn expr() -> Result<(), std::io::Error> {
+    unimplemented!()
+}
+
+#[errify("context {}", build()?)]
+fn format_arg() -> Result<(), std::io::Error> {
+    unimplemented!()
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use errify::errify;
+
+#[errify("context {}", arg, extra_arg)]
+fn func(arg: i32, extra_arg: i32) -> Result<(), std::io::Error> {
+    unimplemented!()
+}
+
+#[errify("context {arg}", name = arg)]
+fn func_named(arg: i32) -> Result<(), std::io::Error> {
+    unimplemented!()
+}
+
+fn main() {}
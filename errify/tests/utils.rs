@@ -4,11 +4,14 @@ use std::{
     ops::Deref,
 };
 
-use errify::WrapErr;
+use errify::{ErrorContext, PanicError, WrapErr};
 
+// Not every test uses it
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct ContextExpr(i32);
 
+#[allow(dead_code)]
 impl ContextExpr {
     pub fn new(i: i32) -> Self {
         Self(i)
@@ -89,3 +92,155 @@ impl WrapErr for ErrorWithContext {
         }
     }
 }
+
+impl From<PanicError> for ErrorWithContext {
+    fn from(err: PanicError) -> Self {
+        Self::new(err)
+    }
+}
+
+impl ErrorContext for ErrorWithContext {
+    fn default_context(&self) -> String {
+        format!("default context of {}", self.msg)
+    }
+}
+
+// Not every test uses it
+#[allow(dead_code)]
+#[cfg(feature = "tracing")]
+pub mod tracing_capture {
+    use std::{
+        fmt::Debug,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Level, Metadata, Subscriber,
+    };
+
+    /// Subscriber that records spans and events as `name = value` fields.
+    #[derive(Default, Clone)]
+    pub struct TracingCapture {
+        spans: Arc<Mutex<Vec<(String, String)>>>,
+        events: Arc<Mutex<Vec<(Level, String)>>>,
+    }
+
+    impl TracingCapture {
+        pub fn spans(&self) -> Vec<(String, String)> {
+            self.spans.lock().unwrap().clone()
+        }
+
+        pub fn events(&self) -> Vec<(Level, String)> {
+            self.events.lock().unwrap().clone()
+        }
+    }
+
+    struct Fields(Vec<String>);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.push(format!("{} = {:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for TracingCapture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields(Vec::new());
+            span.record(&mut fields);
+
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name().to_owned(), fields.0.join(", ")));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(Vec::new());
+            event.record(&mut fields);
+            self.events
+                .lock()
+                .unwrap()
+                .push((*event.metadata().level(), fields.0.join(", ")));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+}
+
+// Not every test uses it
+#[allow(dead_code)]
+#[cfg(feature = "metrics")]
+pub mod metrics_capture {
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+
+    /// Recorder that records counter increments as `name{label=value}`.
+    #[derive(Default, Clone)]
+    pub struct MetricsCapture {
+        counters: Arc<Mutex<Vec<(String, u64)>>>,
+    }
+
+    impl MetricsCapture {
+        pub fn counters(&self) -> Vec<(String, u64)> {
+            self.counters.lock().unwrap().clone()
+        }
+    }
+
+    struct CounterHandle {
+        key: String,
+        counters: Arc<Mutex<Vec<(String, u64)>>>,
+    }
+
+    impl CounterFn for CounterHandle {
+        fn increment(&self, value: u64) {
+            self.counters
+                .lock()
+                .unwrap()
+                .push((self.key.clone(), value));
+        }
+
+        fn absolute(&self, _: u64) {}
+    }
+
+    impl Recorder for MetricsCapture {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let labels = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect::<Vec<_>>()
+                .join(",");
+            Counter::from_arc(Arc::new(CounterHandle {
+                key: format!("{}{{{labels}}}", key.name()),
+                counters: self.counters.clone(),
+            }))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+}
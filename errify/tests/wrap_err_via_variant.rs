@@ -0,0 +1,102 @@
+use errify::{errify, errify_with};
+
+#[derive(Debug)]
+enum MyError {
+    Io(String),
+    WithContext {
+        source: Box<MyError>,
+        context: String,
+    },
+}
+
+errify::wrap_err_via_variant!(MyError::WithContext);
+
+impl MyError {
+    fn contexts(&self) -> Vec<&str> {
+        match self {
+            MyError::Io(_) => Vec::new(),
+            MyError::WithContext { source, context } => {
+                let mut contexts = vec![context.as_str()];
+                contexts.extend(source.contexts());
+                contexts
+            }
+        }
+    }
+
+    fn root(&self) -> &MyError {
+        match self {
+            MyError::WithContext { source, .. } => source.root(),
+            err => err,
+        }
+    }
+}
+
+#[test]
+fn single() {
+    #[errify("Failed to read {path}")]
+    fn read(path: &str) -> Result<(), MyError> {
+        Err(MyError::Io(path.to_owned()))
+    }
+
+    let err = read("config.toml").unwrap_err();
+    assert_eq!(err.contexts(), ["Failed to read config.toml"]);
+    assert!(matches!(err.root(), MyError::Io(path) if path == "config.toml"));
+}
+
+#[test]
+fn nested() {
+    #[errify("Failed to read {path}")]
+    fn read(path: &str) -> Result<(), MyError> {
+        Err(MyError::Io(path.to_owned()))
+    }
+
+    #[errify_with(|| "Failed to load config")]
+    fn load() -> Result<(), MyError> {
+        read("config.toml")?;
+        Ok(())
+    }
+
+    let err = load().unwrap_err();
+    assert_eq!(
+        err.contexts(),
+        ["Failed to load config", "Failed to read config.toml"]
+    );
+    assert!(matches!(err.root(), MyError::Io(path) if path == "config.toml"));
+}
+
+#[test]
+fn thiserror_from_source() {
+    use std::{error::Error, io};
+
+    #[derive(Debug, thiserror::Error)]
+    enum ThisError {
+        #[error("io error")]
+        Io(#[from] io::Error),
+        #[error("{context}")]
+        WithContext {
+            source: Box<ThisError>,
+            context: String,
+        },
+    }
+
+    errify::wrap_err_via_variant!(ThisError::WithContext);
+
+    #[errify("Failed to read {path}")]
+    fn read(path: &str) -> Result<(), ThisError> {
+        Err(io::Error::new(io::ErrorKind::NotFound, path.to_owned()))?
+    }
+
+    let err = read("config.toml").unwrap_err();
+    assert_eq!(err.to_string(), "Failed to read config.toml");
+
+    let source = err.source().unwrap();
+    assert_eq!(source.to_string(), "io error");
+
+    let io_err = source
+        .source()
+        .unwrap()
+        .downcast_ref::<io::Error>()
+        .unwrap();
+    assert_eq!(io_err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(io_err.to_string(), "config.toml");
+}